@@ -59,6 +59,26 @@ impl AABB {
         true
     }
 
+    // Same slab test as `hit`, but hand back the parametric interval the ray
+    // spends inside the box (clipped to [t_min, t_max]).
+    #[must_use]
+    pub fn hit_range(&self, ray: &Ray, mut t_min: f64, mut t_max: f64) -> Option<(f64, f64)> {
+        for a in 0..3 {
+            let inv_dir : f64 = 1.0 / ray.dir[a];
+            let mut t0 = (self.min[a] - ray.origin[a]) * inv_dir;
+            let mut t1 = (self.max[a] - ray.origin[a]) * inv_dir;
+            if inv_dir < 0.0 {
+                swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+
     // calculate a box that includes both box0 and box1
     #[must_use]
     pub fn surrounding_box(box0: AABB, box1: AABB) -> AABB {
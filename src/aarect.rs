@@ -64,14 +64,25 @@ impl AARect {
         )
     }
 
-    fn area(&self) -> f64 {
+    fn random_point(&self) -> Point3 {
         match self.k_axis {
-            Axis::X => (self.p1.y() - self.p0.y()) * (self.p1.z() - self.p0.z()),
-            Axis::Y => (self.p1.x() - self.p0.x()) * (self.p1.z() - self.p0.z()),
-            Axis::Z => (self.p1.x() - self.p0.x()) * (self.p1.y() - self.p0.y()),
+            Axis::X => Point3(
+                self.p0.x(),
+                random::double_range(self.p0.y(), self.p1.y()),
+                random::double_range(self.p0.z(), self.p1.z()),
+            ),
+            Axis::Y => Point3(
+                random::double_range(self.p0.x(), self.p1.x()),
+                self.p0.y(),
+                random::double_range(self.p0.z(), self.p1.z()),
+            ),
+            Axis::Z => Point3(
+                random::double_range(self.p0.x(), self.p1.x()),
+                random::double_range(self.p0.y(), self.p1.y()),
+                self.p1.z(),
+            ),
         }
     }
-
 }
 
 impl From<AARect> for Arc<dyn Hittable + Sync + Send> {
@@ -122,6 +133,10 @@ impl Hittable for AARect {
         ))
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mat);
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         Some(AABB {
             min: self.p0 - self.norm / 10000.,
@@ -141,23 +156,18 @@ impl Hittable for AARect {
     }
 
     fn random(&self, origin: Vec3) -> Vec3 {
-        let random_point = match self.k_axis {
-            Axis::X => Point3(
-                self.p0.x(),
-                random::double_range(self.p0.y(), self.p1.y()),
-                random::double_range(self.p0.z(), self.p1.z()),
-            ),
-            Axis::Y => Point3(
-                random::double_range(self.p0.x(), self.p1.x()),
-                self.p0.y(),
-                random::double_range(self.p0.z(), self.p1.z()),
-            ),
-            Axis::Z => Point3(
-                random::double_range(self.p0.x(), self.p1.x()),
-                random::double_range(self.p0.y(), self.p1.y()),
-                self.p1.z(),
-            ),
-        };
-        random_point - origin
+        self.random_point() - origin
+    }
+
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        Some((self.random_point(), self.norm))
+    }
+
+    fn area(&self) -> f64 {
+        match self.k_axis {
+            Axis::X => (self.p1.y() - self.p0.y()) * (self.p1.z() - self.p0.z()),
+            Axis::Y => (self.p1.x() - self.p0.x()) * (self.p1.z() - self.p0.z()),
+            Axis::Z => (self.p1.x() - self.p0.x()) * (self.p1.y() - self.p0.y()),
+        }
     }
 }
@@ -0,0 +1,218 @@
+use crate::vec3::{Vec3,Point3};
+use crate::hit::Hittable;
+use crate::camera::CameraView;
+use crate::scene::Scene;
+use crate::transform::{Mat4,AnimatedTransform};
+
+use std::sync::Arc;
+
+// How a Track gets from one key to the next
+#[derive(Copy, Clone)]
+pub enum Interpolation {
+    // hold each key's value until the next one
+    Step,
+    Linear,
+    // ease in and out of every key (smoothstep)
+    Smooth,
+    // smooth curve through all the keys (uniform Catmull-Rom spline)
+    CatmullRom,
+}
+
+// Things that can be blended: a weighted sum of some values
+pub trait Animatable: Copy {
+    fn weighted(terms: &[(f64, Self)]) -> Self;
+}
+
+impl Animatable for f64 {
+    fn weighted(terms: &[(f64, Self)]) -> Self {
+        terms.iter().map(|(w, x)| w * x).sum()
+    }
+}
+
+impl Animatable for Vec3 {
+    fn weighted(terms: &[(f64, Self)]) -> Self {
+        terms.iter().fold(Vec3(0.0, 0.0, 0.0), |acc, (w, v)| acc + *w * *v)
+    }
+}
+
+// A value that changes over time, given by keys (time in seconds, value)
+#[derive(Clone)]
+pub struct Track<T: Animatable> {
+    keys: Vec<(f64, T)>,
+    interp: Interpolation,
+}
+
+impl<T: Animatable> Track<T> {
+    #[must_use]
+    pub fn new(interp: Interpolation) -> Self {
+        Self { keys: vec![], interp }
+    }
+
+    // Doesn't change
+    #[must_use]
+    pub fn constant(value: T) -> Self {
+        Self::new(Interpolation::Step).key(0.0, value)
+    }
+
+    #[must_use]
+    pub fn key(mut self, time: f64, value: T) -> Self {
+        let i = self.keys.partition_point(|k| k.0 <= time);
+        self.keys.insert(i, (time, value));
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the track has no keys
+    #[must_use]
+    pub fn value(&self, time: f64) -> T {
+        assert!(!self.keys.is_empty(), "Track has no keys");
+        let n = self.keys.len();
+        if time <= self.keys[0].0 {
+            return self.keys[0].1;
+        }
+        if time >= self.keys[n - 1].0 {
+            return self.keys[n - 1].1;
+        }
+
+        let i = self.keys.partition_point(|k| k.0 <= time) - 1;
+        let (t0, p1) = self.keys[i];
+        let (t1, p2) = self.keys[i + 1];
+        let t = (time - t0) / (t1 - t0);
+        match self.interp {
+            Interpolation::Step => p1,
+            Interpolation::Linear => T::weighted(&[(1.0 - t, p1), (t, p2)]),
+            Interpolation::Smooth => {
+                let s = t * t * (3.0 - 2.0 * t);
+                T::weighted(&[(1.0 - s, p1), (s, p2)])
+            },
+            Interpolation::CatmullRom => {
+                // missing neighbours at the ends are taken to be the ends
+                let p0 = self.keys[i.saturating_sub(1)].1;
+                let p3 = self.keys[(i + 2).min(n - 1)].1;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                T::weighted(&[
+                    (0.5 * (-t3 + 2.0 * t2 - t), p0),
+                    (0.5 * (3.0 * t3 - 5.0 * t2 + 2.0), p1),
+                    (0.5 * (-3.0 * t3 + 4.0 * t2 + t), p2),
+                    (0.5 * (t3 - t2), p3),
+                ])
+            },
+        }
+    }
+}
+
+// Camera that moves around
+#[derive(Clone)]
+pub struct CameraAnimation {
+    pub lookfrom: Track<Point3>,
+    pub lookat: Track<Point3>,
+    pub vfov: Track<f64>,
+    pub focus_dist: Track<f64>,
+}
+
+impl CameraAnimation {
+    // Camera that stays put
+    #[must_use]
+    pub fn fixed(view: &CameraView) -> Self {
+        Self {
+            lookfrom: Track::constant(view.lookfrom),
+            lookat: Track::constant(view.lookat),
+            vfov: Track::constant(view.vfov),
+            focus_dist: Track::constant(view.focus_dist),
+        }
+    }
+
+    // Circle around lookat, starting from lookfrom, making a number of turns
+    // per second for duration seconds
+    #[must_use]
+    pub fn turntable(view: &CameraView, turns: f64, duration: f64) -> Self {
+        let offset = view.lookfrom - view.lookat;
+        let mut lookfrom = Track::new(Interpolation::CatmullRom);
+        // plenty of keys, so the spline stays close to the circle
+        let n_keys = (64.0 * (turns * duration).abs()).ceil().max(4.0) as usize;
+        for k in 0..=n_keys {
+            let time = duration * k as f64 / n_keys as f64;
+            let m = Mat4::rotate_y(360.0 * turns * time);
+            lookfrom = lookfrom.key(time, view.lookat + m.vector(offset));
+        }
+        Self { lookfrom, ..Self::fixed(view) }
+    }
+
+    #[must_use]
+    pub fn view(&self, time: f64) -> CameraView {
+        CameraView {
+            lookfrom: self.lookfrom.value(time),
+            lookat: self.lookat.value(time),
+            vfov: self.vfov.value(time),
+            focus_dist: self.focus_dist.value(time),
+        }
+    }
+}
+
+// Object transform built up from animated translation, rotation (about x,
+// then y, then z, in degrees) and scale
+#[derive(Clone)]
+pub struct TransformTrack {
+    pub translate: Track<Vec3>,
+    pub rotate: Track<Vec3>,
+    pub scale: Track<Vec3>,
+}
+
+impl Default for TransformTrack {
+    fn default() -> Self {
+        Self {
+            translate: Track::constant(Vec3(0.0, 0.0, 0.0)),
+            rotate: Track::constant(Vec3(0.0, 0.0, 0.0)),
+            scale: Track::constant(Vec3(1.0, 1.0, 1.0)),
+        }
+    }
+}
+
+impl TransformTrack {
+    // Keyframes per frame for the motion blur
+    const SHUTTER_KEYS : usize = 8;
+
+    #[must_use]
+    pub fn matrix(&self, time: f64) -> Mat4 {
+        let r = self.rotate.value(time);
+        Mat4::translate(self.translate.value(time))
+            * Mat4::rotate_z(r.z())
+            * Mat4::rotate_y(r.y())
+            * Mat4::rotate_x(r.x())
+            * Mat4::scale(self.scale.value(time))
+    }
+
+    // obj placed by this track while the shutter is open, following the
+    // curves closely enough for motion blur
+    #[must_use]
+    pub fn apply(&self, obj: Arc<dyn Hittable + Sync + Send>, time0: f64, time1: f64)
+                 -> AnimatedTransform {
+        let keys: Vec<(f64, Mat4)> = (0..=Self::SHUTTER_KEYS).map(|k| {
+            let time = time0 + (time1 - time0) * k as f64 / Self::SHUTTER_KEYS as f64;
+            (time, self.matrix(time))
+        }).collect();
+        AnimatedTransform::new(obj, &keys)
+    }
+}
+
+// A scene that changes over time. build gets the shutter interval of a frame
+// and returns the scene as it is during it; rays are timed within that
+// interval, so anything keyed on Ray::time (e.g. TransformTrack::apply)
+// blurs.
+pub struct AnimatedScene {
+    pub camera: CameraAnimation,
+    pub build: Box<dyn Fn(f64, f64) -> Scene + Sync + Send>,
+}
+
+impl AnimatedScene {
+    // The same scene, from the same place, every frame
+    #[must_use]
+    pub fn still(scene: Scene, focus_dist: f64) -> Self {
+        Self {
+            camera: CameraAnimation::fixed(&CameraView::from_scene(&scene, focus_dist)),
+            build: Box::new(move |_, _| scene.clone()),
+        }
+    }
+}
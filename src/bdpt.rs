@@ -0,0 +1,570 @@
+// Bidirectional path tracing (Veach 1997, much as pbrt does it): a path from
+// the camera and one from a light, joined up every way they can be, with
+// multiple importance sampling weighing the ways against each other.
+//
+// Media aren't followed, apart from shadow rays seeing through them by their
+// transmittance, and neither is the medium stack; scenes that need them are
+// better off with the path tracer.
+
+use super::{Lobe, PathState, Radiance, Tracer, light_index};
+
+use hawxide::{Color, INFINITY, PI, Point3, Ray, Scene, Vec3, random};
+use hawxide::camera::CameraModel;
+use hawxide::hit::{HitRecord, Hittable};
+use hawxide::material::ScatterRecord;
+use hawxide::onb::OrthoNormalBasis;
+use hawxide::vec3::dot;
+
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Kind {
+    Camera,
+    // where a path of light starts
+    Light,
+    Surface,
+}
+
+#[derive(Clone)]
+struct Vertex {
+    kind: Kind,
+    p: Point3,
+    // geometric normal, facing the way the path arrived; zero at the camera
+    n: Vec3,
+    // the surface there. Light vertices keep theirs for the emission.
+    hr: Option<HitRecord>,
+    // direction the path arrived along
+    wi: Vec3,
+    time: f64,
+    // light (or importance) carried this far
+    beta: Color,
+    // specular, so it can't be joined to anything
+    delta: bool,
+    // density of getting here (per unit area) going forward along the path,
+    // and going the other way
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    // which of scene.lights it's on, if any
+    light: Option<usize>,
+}
+
+// hr as if the ray had arrived going in direction dir
+fn facing(hr: &HitRecord, dir: Vec3) -> HitRecord {
+    let mut hr = hr.clone();
+    if dot(dir, hr.norm) > 0.0 {
+        hr.front_face = !hr.front_face;
+        hr.norm = -hr.norm;
+        hr.shading_geo.n = -hr.shading_geo.n;
+    }
+    hr
+}
+
+impl Vertex {
+    fn camera(p: Point3, time: f64, beta: Color) -> Self {
+        Self {
+            kind: Kind::Camera, p, n: Vec3::new(), hr: None, wi: Vec3::new(), time, beta,
+            delta: false, pdf_fwd: 0.0, pdf_rev: 0.0, light: None,
+        }
+    }
+
+    fn surface(hr: HitRecord, r: &Ray, beta: Color) -> Self {
+        Self {
+            kind: Kind::Surface, p: hr.p, n: hr.norm, wi: r.dir.unit_vector(), time: r.time,
+            hr: Some(hr), beta, delta: false, pdf_fwd: 0.0, pdf_rev: 0.0, light: None,
+        }
+    }
+
+    // The material's scatter for a path arriving along d_in. None if there's
+    // no surface, it doesn't scatter that way, or it can't be evaluated
+    // toward a given direction (see Material::deterministic).
+    fn scatter(&self, d_in: Vec3) -> Option<(Ray, HitRecord, ScatterRecord)> {
+        let hr = facing(self.hr.as_ref()?, d_in);
+        if !hr.mat.deterministic() {
+            return None;
+        }
+        let r_in = Ray::new(self.p - d_in, d_in, self.time);
+        let sr = hr.mat.scatter(&r_in, &hr)?;
+        Some((r_in, hr, sr))
+    }
+
+    // BSDF times the cosine toward wo, for a path arriving along d_in and
+    // leaving along wo. Nothing for specular surfaces, which only go one way.
+    fn f_cos(&self, d_in: Vec3, wo: Vec3) -> Color {
+        match self.scatter(d_in) {
+            Some((r_in, hr, sr)) if sr.specular_ray.is_none() => hr.mat.scattering_value(
+                &r_in, &hr, sr.attenuation, &Ray::new(self.p, wo, self.time),
+            ),
+            _ => Color(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Density (per solid angle) of the material sending a path arriving
+    // along d_in off along wo
+    fn pdf_dir(&self, d_in: Vec3, wo: Vec3) -> f64 {
+        match self.scatter(d_in) {
+            Some((_, _, sr)) if sr.specular_ray.is_none() => sr.pdf.value(wo),
+            _ => 0.0,
+        }
+    }
+
+    // A density per solid angle as seen from here, as one per unit area at
+    // next
+    fn to_area(&self, pdf: f64, next: &Vertex) -> f64 {
+        let d = next.p - self.p;
+        let dist_squared = d.len_squared();
+        if dist_squared <= 0.0 {
+            return 0.0;
+        }
+        let pdf = pdf / dist_squared;
+        if next.kind == Kind::Camera {
+            pdf
+        } else {
+            pdf * dot(next.n, d).abs() / dist_squared.sqrt()
+        }
+    }
+
+    // Density (per unit area) of the path going from here to next, having
+    // come from prev
+    fn pdf(&self, cam: &dyn CameraModel, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let to_next = next.p - self.p;
+        let pdf = match self.kind {
+            Kind::Light => return self.pdf_light(next),
+            Kind::Camera => cam.pdf_dir(to_next),
+            Kind::Surface => match prev {
+                Some(prev) => self.pdf_dir((self.p - prev.p).unit_vector(), to_next.unit_vector()),
+                None => 0.0,
+            },
+        };
+        self.to_area(pdf, next)
+    }
+
+    // Density (per unit area) of light leaving here, on a light, reaching next
+    fn pdf_light(&self, next: &Vertex) -> f64 {
+        let dir = (next.p - self.p).unit_vector();
+        self.to_area(dot(self.n, dir).abs() / PI, next)
+    }
+
+    // Density (per unit area) of a path of light starting here
+    fn pdf_light_origin(&self, scene: &Scene) -> f64 {
+        let Some(l) = self.light else { return 0.0; };
+        let area = scene.lights.objects[l].area();
+        if area > 0.0 {
+            #[allow(clippy::cast_precision_loss)]
+            let n = scene.lights.objects.len() as f64;
+            1.0 / (n * area)
+        } else {
+            0.0
+        }
+    }
+
+    // Light given off here toward the previous vertex of the camera's path
+    fn emitted(&self) -> Color {
+        match &self.hr {
+            Some(hr) => hr.mat.emitted(
+                &Ray::new(self.p - self.wi, self.wi, self.time), hr, hr.u, hr.v, hr.p,
+            ),
+            None => Color(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// Light leaving point p of the scene in direction dir, and the surface that
+// gives it off. Found from the world rather than scene.lights, which may
+// only have the lights' shapes.
+fn emission(scene: &Scene, p: Point3, dir: Vec3, time: f64) -> Option<(Color, HitRecord)> {
+    const EPS : f64 = 1e-3;
+    let r = Ray::new(p + EPS * dir, -dir, time);
+    let hr = scene.world.hit(&r, 0.0, 2.0 * EPS)?;
+    let le = hr.mat.emitted(&r, &hr, hr.u, hr.v, hr.p);
+    (!le.near_zero()).then_some((le, hr))
+}
+
+// How much light gets from a to b
+fn visibility(scene: &Scene, a: Point3, b: Point3, time: f64) -> f64 {
+    let d = b - a;
+    let dist = d.len();
+    scene.world.transmittance(&Ray::new(a, d / dist, time), 0.001, dist - 0.001)
+}
+
+// Extend a path along r, which carries beta and was picked with density
+// pdf_dir (per solid angle). The bounces are limited, and rouletted, as in
+// the path tracer. Returns what's carried out of the scene, if r escapes.
+fn random_walk(scene: &Scene, r: Ray, beta: Color, pdf_dir: f64, path: &mut Vec<Vertex>,
+               tracer: &Tracer, from_camera: bool) -> Option<Color> {
+    let mut r = r;
+    let mut pdf_dir = pdf_dir;
+    let mut state = PathState::new();
+
+    loop {
+        let Some(hr) = scene.world.hit(&r, 0.001, INFINITY) else {
+            return Some(beta * state.throughput);
+        };
+        // the shadow rays joining paths up go straight through holes (see
+        // Material::transparent), so the walks must too, rather than make
+        // a vertex there that the same light could be counted through again
+        if hr.mat.transparent(&r, &hr) {
+            r = Ray::new(hr.p, r.dir, r.time);
+            continue;
+        }
+        let prev = path.len() - 1;
+        // the camera's path may find a light, which the light's paths could
+        // have started on
+        let light = if from_camera && !hr.mat.emitted(&r, &hr, hr.u, hr.v, hr.p).near_zero() {
+            light_index(scene, &r, &hr)
+        } else {
+            None
+        };
+        let mut v = Vertex::surface(hr.clone(), &r, beta * state.throughput);
+        v.light = light;
+        v.pdf_fwd = path[prev].to_area(pdf_dir, &v);
+        path.push(v);
+        let cur = path.len() - 1;
+
+        let mut sr = hr.mat.scatter(&r, &hr)?;
+
+        if let Some(spec_r) = sr.specular_ray {
+            let lobe = Lobe::of(&r, &hr, spec_r.dir, true);
+            path[cur].delta = true;
+            path[prev].pdf_rev = 0.0;
+            pdf_dir = 0.0;
+            state = state.bounce(lobe, sr.attenuation, &tracer.limits)?;
+            r = Ray::new(spec_r.origin, spec_r.dir, r.time);
+            continue;
+        }
+
+        let pdf = sr.pdf.clone();
+        let wo = pdf.generate(&mut sr).unit_vector();
+        pdf_dir = pdf.value(wo);
+        if pdf_dir.is_nan() || pdf_dir <= 0.0 || pdf_dir.is_infinite() {
+            tracer.bad_pdfs.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let scattered = Ray::new(hr.p, wo, r.time);
+        // NOTE: generate may have updated sr.attenuation
+        let value = hr.mat.scattering_value(&r, &hr, sr.attenuation, &scattered);
+        let lobe = Lobe::of(&r, &hr, wo, false);
+
+        // the same bounce taken backwards
+        let rev = path[cur].pdf_dir(-wo, -path[cur].wi);
+        path[prev].pdf_rev = path[cur].to_area(rev, &path[prev]);
+
+        state = state.bounce(lobe, value / pdf_dir, &tracer.limits)?;
+        r = scattered;
+        if !hr.mat.deterministic() {
+            // only its sampled direction is known, so nothing joins up here,
+            // as if it were specular
+            path[cur].delta = true;
+            pdf_dir = 0.0;
+        }
+    }
+}
+
+// Light leaving a random point of a random light, in a random direction
+// (cosine weighted about the way it shines), as photon mapping and BDPT's
+// light paths start out
+pub struct Emission {
+    pub light: usize,
+    pub p: Point3,
+    // the side of the light that shines
+    pub n: Vec3,
+    pub dir: Vec3,
+    pub le: Color,
+    pub hr: HitRecord,
+    // densities of the point (per unit area, including picking the light)
+    // and the direction (per solid angle)
+    pub pdf_origin: f64,
+    pub pdf_dir: f64,
+}
+
+impl Emission {
+    #[must_use]
+    pub fn sample(scene: &Scene, time: f64) -> Option<Self> {
+        let n_lights = scene.lights.objects.len();
+        if n_lights == 0 {
+            return None;
+        }
+        let l = random::uint(0, n_lights - 1);
+        let light = &scene.lights.objects[l];
+        let (p, n) = light.sample_surface()?;
+
+        // the light's shape may not know which way it shines
+        let side = [n, -n].into_iter().find(|side| emission(scene, p, *side, time).is_some())?;
+        let mut uvw = OrthoNormalBasis::new();
+        uvw.build_from_w(side);
+        let dir = uvw.local_v(random::cosine_direction()).unit_vector();
+        let (le, hr) = emission(scene, p, dir, time)?;
+
+        let cos_theta = dot(side, dir);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let pdf_origin = 1.0 / (n_lights as f64 * light.area());
+        Some(Self { light: l, p, n: side, dir, le, hr, pdf_origin, pdf_dir: cos_theta / PI })
+    }
+
+    // What the ray leaving the light carries, for the densities it was
+    // picked with
+    #[must_use]
+    pub fn beta(&self) -> Color {
+        self.le * (dot(self.n, self.dir) / (self.pdf_origin * self.pdf_dir))
+    }
+}
+
+// A path of light from a random point on a random light
+fn light_path(scene: &Scene, time: f64, tracer: &Tracer) -> Vec<Vertex> {
+    let mut path = vec![];
+    let Some(e) = Emission::sample(scene, time) else { return path; };
+    let beta = e.beta();
+    path.push(Vertex {
+        kind: Kind::Light, p: e.p, n: e.n, hr: Some(e.hr), wi: Vec3::new(), time,
+        beta: e.le / e.pdf_origin, delta: false, pdf_fwd: e.pdf_origin, pdf_rev: 0.0,
+        light: Some(e.light),
+    });
+    random_walk(scene, Ray::new(e.p, e.dir, time), beta, e.pdf_dir, &mut path, tracer, false);
+    path
+}
+
+// A new vertex on a random light, as seen from pt, with what it gives off
+// that way
+fn sample_light(scene: &Scene, pt: &Vertex) -> Option<Vertex> {
+    let n_lights = scene.lights.objects.len();
+    if n_lights == 0 {
+        return None;
+    }
+    let l = random::uint(0, n_lights - 1);
+    let light = &scene.lights.objects[l];
+    let (p, n) = light.sample_surface()?;
+    let (le, hr) = emission(scene, p, (pt.p - p).unit_vector(), pt.time)?;
+    let n = if dot(n, pt.p - p) < 0.0 { -n } else { n };
+    let mut v = Vertex {
+        kind: Kind::Light, p, n, hr: Some(hr), wi: Vec3::new(), time: pt.time,
+        beta: Color(0.0, 0.0, 0.0), delta: false, pdf_fwd: 0.0, pdf_rev: 0.0,
+        light: Some(l),
+    };
+    v.pdf_fwd = v.pdf_light_origin(scene);
+    if v.pdf_fwd <= 0.0 {
+        return None;
+    }
+    v.beta = le / v.pdf_fwd;
+    Some(v)
+}
+
+// Light carried by the path joining the first s vertices of the light's path
+// to the first t of the camera's, unweighted. Some ways of joining them pick
+// a new end vertex, on a light or the lens, which comes back too. So does
+// where on the image the path lands, if it doesn't go through the pixel the
+// camera's path started from.
+#[allow(clippy::type_complexity)]
+fn connect(scene: &Scene, cam: &dyn CameraModel, light: &[Vertex], camera: &[Vertex],
+           s: usize, t: usize) -> Option<(Color, Option<Vertex>, Option<(f64, f64)>)> {
+    if s == 0 {
+        // the camera's path found a light by itself
+        let pt = &camera[t - 1];
+        return Some((pt.beta * pt.emitted(), None, None));
+    }
+    if t == 1 {
+        // straight into the camera
+        let qs = &light[s - 1];
+        if qs.delta {
+            return None;
+        }
+        let ls = cam.sample_lens(qs.p)?;
+        let we = ls.importance / ls.pdf;
+        let sampled = Vertex::camera(ls.origin, qs.time, Color(we, we, we));
+        let dir = (ls.origin - qs.p).unit_vector();
+        let c = qs.beta * qs.f_cos(qs.wi, dir) * sampled.beta
+            * visibility(scene, qs.p, ls.origin, qs.time);
+        return Some((c, Some(sampled), Some((ls.s, ls.t))));
+    }
+    let pt = &camera[t - 1];
+    if pt.delta {
+        return None;
+    }
+    if s == 1 {
+        // a new point on a light, as in next event estimation
+        let sampled = sample_light(scene, pt)?;
+        let d = sampled.p - pt.p;
+        let dist_squared = d.len_squared();
+        let dir = d.unit_vector();
+        let cos_light = dot(sampled.n, dir).abs();
+        let c = pt.beta * pt.f_cos(pt.wi, dir) * sampled.beta * (cos_light / dist_squared)
+            * visibility(scene, pt.p, sampled.p, pt.time);
+        return Some((c, Some(sampled), None));
+    }
+    let qs = &light[s - 1];
+    if qs.delta {
+        return None;
+    }
+    let d = pt.p - qs.p;
+    let dist_squared = d.len_squared();
+    let dir = d.unit_vector();
+    let c = qs.beta * qs.f_cos(qs.wi, dir) * pt.f_cos(pt.wi, -dir) * pt.beta / dist_squared;
+    if c.near_zero() {
+        return Some((c, None, None));
+    }
+    Some((c * visibility(scene, qs.p, pt.p, pt.time), None, None))
+}
+
+// Balance heuristic weight for the (s, t) way of making the path, against
+// every other way of making it. Worked out from how the vertex densities
+// change as the join moves along the path.
+fn mis_weight(scene: &Scene, cam: &dyn CameraModel, light: &[Vertex], camera: &[Vertex],
+              sampled: Option<&Vertex>, s: usize, t: usize) -> f64 {
+    if s + t == 2 {
+        return 1.0;
+    }
+    let lv = |i: usize| if s == 1 && i == 0 { sampled.unwrap_or(&light[0]) } else { &light[i] };
+    let cv = |i: usize| if t == 1 && i == 0 { sampled.unwrap_or(&camera[0]) } else { &camera[i] };
+
+    let pt = cv(t - 1);
+    let pt_minus = (t > 1).then(|| cv(t - 2));
+    let qs = (s > 0).then(|| lv(s - 1));
+    let qs_minus = (s > 1).then(|| lv(s - 2));
+
+    if s == 0 && pt.pdf_light_origin(scene) <= 0.0 {
+        // a light that can't be sampled: nothing else could have found it
+        return 1.0;
+    }
+
+    let mut cam_fwd: Vec<f64> = (0..t).map(|i| cv(i).pdf_fwd).collect();
+    let mut cam_rev: Vec<f64> = (0..t).map(|i| cv(i).pdf_rev).collect();
+    let mut cam_delta: Vec<bool> = (0..t).map(|i| cv(i).delta).collect();
+    let light_fwd: Vec<f64> = (0..s).map(|i| lv(i).pdf_fwd).collect();
+    let mut light_rev: Vec<f64> = (0..s).map(|i| lv(i).pdf_rev).collect();
+    let mut light_delta: Vec<bool> = (0..s).map(|i| lv(i).delta).collect();
+
+    // the densities at the join, going the other way
+    cam_delta[t - 1] = false;
+    cam_rev[t - 1] = match qs {
+        Some(qs) => qs.pdf(cam, qs_minus, pt),
+        None => pt.pdf_light_origin(scene),
+    };
+    if let Some(pt_minus) = pt_minus {
+        cam_rev[t - 2] = match qs {
+            Some(qs) => pt.pdf(cam, Some(qs), pt_minus),
+            None => pt.pdf_light(pt_minus),
+        };
+    }
+    if let Some(qs) = qs {
+        light_delta[s - 1] = false;
+        light_rev[s - 1] = pt.pdf(cam, pt_minus, qs);
+        if let Some(qs_minus) = qs_minus {
+            light_rev[s - 2] = qs.pdf(cam, Some(pt), qs_minus);
+        }
+    }
+
+    // specular vertices have no density, and no way to be joined either
+    let remap = |f: f64| if f == 0.0 { 1.0 } else { f };
+    let mut sum = 0.0;
+    let mut ri = 1.0;
+    for i in (1..t).rev() {
+        ri *= remap(cam_rev[i]) / remap(cam_fwd[i]);
+        // i == 1 is tracing straight to the camera
+        if !cam_delta[i] && !cam_delta[i - 1] && (i > 1 || cam.has_importance()) {
+            sum += ri;
+        }
+    }
+    ri = 1.0;
+    for i in (0..s).rev() {
+        ri *= remap(light_rev[i]) / remap(light_fwd[i]);
+        let delta_before = i > 0 && light_delta[i - 1];
+        if !light_delta[i] && !delta_before {
+            sum += ri;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
+
+// Light traced into the camera lands all over the image, so it's gathered
+// here, by pixel, until the render's done
+pub struct Splats {
+    width: usize,
+    height: usize,
+    pixels: Vec<Mutex<Radiance>>,
+}
+
+impl Splats {
+    pub fn new(width: usize, height: usize, n_lights: usize) -> Self {
+        Self {
+            width, height,
+            pixels: (0..width * height).map(|_| Mutex::new(Radiance::zero(n_lights))).collect(),
+        }
+    }
+
+    // Image coordinates (s, t) to a pixel, rows from the top, the same way
+    // render() maps pixels to rays
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    fn add(&self, s: f64, t: f64, rad: &Radiance) {
+        let x = ((s * self.width as f64) as usize).min(self.width - 1);
+        let y = ((t * self.height as f64) as usize).min(self.height - 1);
+        let pixel = (self.height - 1 - y) * self.width + x;
+        let mut p = self.pixels[pixel].lock().expect("splat lock poisoned");
+        *p = p.clone().add(rad);
+    }
+
+    // What landed on pixel (x, y), per sample
+    pub fn get(&self, x: usize, y: usize) -> Radiance {
+        self.pixels[y * self.width + x].lock().expect("splat lock poisoned").clone()
+    }
+}
+
+// The light along camera ray r, by every join of a path from the camera and
+// one from a light. Joins straight to the camera go into splats instead.
+pub fn bdpt(r: Ray, scene: &Scene, cam: &dyn CameraModel, tracer: &Tracer,
+            splats: &Splats) -> Radiance {
+    let n_lights = tracer.n_lights;
+    let mut rad = Radiance::zero(n_lights);
+    let time = r.time;
+
+    let mut camera = vec![Vertex::camera(r.origin, time, Color(1.0, 1.0, 1.0))];
+    let pdf_dir = cam.pdf_dir(r.dir);
+    if let Some(beta) = random_walk(scene, r, Color(1.0, 1.0, 1.0), pdf_dir, &mut camera,
+                                    tracer, true) {
+        // TODO(oren): this doesn't make a great deal of sense
+        rad.add_light(camera.len() - 1, beta * scene.background, None);
+    }
+    let light = light_path(scene, time, tracer);
+
+    let max_depth = tracer.limits.max_depth as usize;
+    for t in 1..=camera.len() {
+        for s in 0..=light.len().max(1) {
+            if s + t < 2 || (s == 1 && t == 1) || s + t - 2 >= max_depth {
+                continue;
+            }
+            if t == 1 && !cam.has_importance() {
+                continue;
+            }
+            if s > light.len() && s != 1 {
+                continue;
+            }
+            let Some((c, sampled, raster)) = connect(scene, cam, &light, &camera, s, t) else {
+                continue;
+            };
+            if c.near_zero() {
+                continue;
+            }
+            let c = c * mis_weight(scene, cam, &light, &camera, sampled.as_ref(), s, t);
+            let which = match s {
+                0 => camera[t - 1].light,
+                1 => sampled.as_ref().and_then(|v| v.light),
+                _ => light[0].light,
+            }.filter(|_| n_lights > 0);
+
+            if let Some((u, v)) = raster {
+                let mut splat = Radiance::zero(n_lights);
+                splat.add_light(s - 1, c, which);
+                let total = splat.total();
+                if total.is_nan() || !(total.r() + total.g() + total.b()).is_finite() {
+                    tracer.bad_samples.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                splats.add(u, v, &splat);
+            } else {
+                rad.add_light(s + t - 2, c, which);
+            }
+        }
+    }
+    rad
+}
@@ -59,6 +59,10 @@ impl Hittable for Boxx {
         self.sides.hit(r, t_min, t_max)
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.sides.materials(f);
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         Some(AABB {
             min: self.box_min,
@@ -2,6 +2,7 @@ use crate::ray::Ray;
 use crate::hit::{HitRecord,Hittable};
 use crate::hittable_list::HittableList;
 use crate::aabb::AABB;
+use crate::material::Material;
 use crate::util::random;
 
 use std::sync::Arc;
@@ -11,41 +12,58 @@ pub struct BVHNode {
     left: Arc<dyn Hittable + Sync + Send>,
     right: Arc<dyn Hittable + Sync + Send>,
     bbox: AABB,
+    // Object IDs of the children when they are leaves of a BVH built from a
+    // list (0 for inner nodes and for slices)
+    ids: (u32, u32),
+    // The list this was built from, in its original order; only kept at the
+    // root so materials can be walked in the order they were added
+    objects: Vec<Arc<dyn Hittable + Sync + Send>>,
 }
 
 impl BVHNode {
     #[must_use]
     pub fn new(list: &HittableList, time0: f64, time1: f64)
                -> Self {
-        Self::new_slice(&list.objects, time0, time1)
+        let objects : Vec<_> = list.objects.iter().enumerate()
+            .map(|(i, o)| (i as u32 + 1, o.clone()))
+            .collect();
+        Self {
+            objects: list.objects.clone(),
+            ..Self::build(&objects, time0, time1)
+        }
     }
 
-    /// # Panics
-    ///
-    /// Will panic if either the left or right child has no bounding box
     #[must_use]
     pub fn new_slice(src_objects: &[Arc<dyn Hittable + Sync + Send>], time0: f64, time1: f64)
                      -> Self {
-        let mut objects = Vec::<Arc<dyn Hittable + Sync + Send>>::new();
-        for o in src_objects {
-            objects.push(o.clone());
-        }
+        let objects : Vec<_> = src_objects.iter().map(|o| (0, o.clone())).collect();
+        Self::build(&objects, time0, time1)
+    }
 
-        let comparator = |a: &Arc<dyn Hittable + Sync + Send>, b: &Arc<dyn Hittable + Sync + Send>| -> Ordering {
-            box_compare(a, b, random::uint(0, 2))
+    /// # Panics
+    ///
+    /// Will panic if either the left or right child has no bounding box
+    fn build(src_objects: &[(u32, Arc<dyn Hittable + Sync + Send>)], time0: f64, time1: f64)
+             -> Self {
+        let mut objects = src_objects.to_vec();
+
+        let axis = random::uint(0, 2);
+        let comparator = |a: &(u32, Arc<dyn Hittable + Sync + Send>), b: &(u32, Arc<dyn Hittable + Sync + Send>)| -> Ordering {
+            box_compare(&a.1, &b.1, axis)
         };
 
-        let (left, right) : (Arc<dyn Hittable + Sync + Send>, Arc<dyn Hittable + Sync + Send>) = match objects.len() {
-            1 => (objects[0].clone(), objects[0].clone()),
+        let (left, right, ids) : (Arc<dyn Hittable + Sync + Send>, Arc<dyn Hittable + Sync + Send>, (u32, u32)) = match objects.len() {
+            1 => (objects[0].1.clone(), objects[0].1.clone(), (objects[0].0, objects[0].0)),
             2 =>  {
                 objects.sort_by(comparator);
-                (objects[0].clone(), objects[1].clone())
+                (objects[0].1.clone(), objects[1].1.clone(), (objects[0].0, objects[1].0))
             },
             _ => {
                 objects.sort_by(comparator);
                 let mid = objects.len() / 2;
-                (BVHNode::new_slice(&objects[0..mid], time0, time1).into(),
-                 BVHNode::new_slice(&objects[mid..], time0, time1).into())
+                (BVHNode::build(&objects[0..mid], time0, time1).into(),
+                 BVHNode::build(&objects[mid..], time0, time1).into(),
+                 (0, 0))
             }
         };
 
@@ -59,6 +77,8 @@ impl BVHNode {
             left,
             right,
             bbox: AABB::surrounding_box(box_left.unwrap(), box_right.unwrap()),
+            ids,
+            objects: Vec::new(),
         }
     }
 }
@@ -85,14 +105,21 @@ impl Hittable for BVHNode {
             return None;
         }
 
+        let tag = |mut hr: HitRecord, id: u32| {
+            if id != 0 {
+                hr.object_id = id;
+            }
+            hr
+        };
+
         if let Some(hr_l) = self.left.hit(r, t_min, t_max) {
             if let Some(hr_r) = self.right.hit(r, t_min, hr_l.t) {
-                Some(hr_r)
+                Some(tag(hr_r, self.ids.1))
             } else {
-                Some(hr_l)
+                Some(tag(hr_l, self.ids.0))
             }
         } else {
-            self.right.hit(r, t_min, t_max)
+            self.right.hit(r, t_min, t_max).map(|hr| tag(hr, self.ids.1))
         }
     }
 
@@ -102,4 +129,30 @@ impl Hittable for BVHNode {
             max: self.bbox.max,
         })
     }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        if self.objects.is_empty() {
+            self.left.materials(f);
+            if !Arc::ptr_eq(&self.left, &self.right) {
+                self.right.materials(f);
+            }
+        } else {
+            for obj in &self.objects {
+                obj.materials(f);
+            }
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return 1.0;
+        }
+
+        let tr = self.left.transmittance(r, t_min, t_max);
+        if tr <= 0.0 || Arc::ptr_eq(&self.left, &self.right) {
+            tr
+        } else {
+            tr * self.right.transmittance(r, t_min, t_max)
+        }
+    }
 }
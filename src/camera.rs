@@ -6,7 +6,481 @@ use crate::ray::Ray;
 use crate::util;
 use crate::util::random;
 use crate::scene::Scene;
+use crate::transform::Mat4;
+use crate::exposure::Shutter;
 
+use std::sync::Arc;
+use std::path::Path;
+
+
+// Where a camera is and what it's looking at, independent of the image it
+// makes. Animations produce one of these per frame.
+#[derive(Copy, Clone)]
+pub struct CameraView {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+}
+
+impl CameraView {
+    #[must_use]
+    pub fn from_scene(scene: &Scene, focus_dist: f64) -> Self {
+        Self {
+            lookfrom: scene.lookfrom,
+            lookat: scene.lookat,
+            vfov: scene.vfov,
+            focus_dist,
+        }
+    }
+
+    // Focus on whatever is at (s, t) in the image at the given time (say,
+    // when the shutter opens), as a pinhole camera would see it. Keeps the
+    // current focus distance if there's nothing there.
+    #[must_use]
+    pub fn autofocus(&self, scene: &Scene, vup: Vec3, aspect_ratio: f64,
+                     s: f64, t: f64, time: f64) -> Self {
+        let frame = CameraFrame::new(self, vup, &Shutter::new(0.0, 0.0));
+        let h = (util::degrees_to_radians(self.vfov) / 2.0).tan();
+        // one unit along the view axis, so t is the distance to the focus plane
+        let dir = frame.to_world(Vec3(
+            (2.0 * s - 1.0) * h * aspect_ratio,
+            (2.0 * t - 1.0) * h,
+            1.0,
+        ));
+        let hit = scene.world.hit(&Ray::new(frame.origin, dir, time), 0.001, util::INFINITY);
+        match hit {
+            Some(hr) => {
+                eprintln!("Autofocus: {:.3}", hr.t);
+                Self { focus_dist: hr.t, ..*self }
+            },
+            None => *self,
+        }
+    }
+}
+
+// Something that turns points on the image into rays
+pub trait CameraModel {
+    // Ray through image coordinates (s, t), both in [0,1] with (0,0) at the
+    // bottom left. None where the projection doesn't cover the image (e.g.
+    // outside a fisheye's image circle); those pixels come out black.
+    fn ray(&self, s: f64, t: f64) -> Option<Ray>;
+
+    // For tracing light into the camera: a point on the lens that sees p,
+    // with where p lands on the image. None if it's out of frame, or the
+    // camera doesn't support it (only the perspective camera does).
+    fn sample_lens(&self, _p: Point3) -> Option<LensSample> {
+        None
+    }
+
+    // Density (per solid angle) of ray() heading off in direction dir, for
+    // weighing the camera's rays against light traced into it
+    fn pdf_dir(&self, _dir: Vec3) -> f64 {
+        0.0
+    }
+
+    fn has_importance(&self) -> bool {
+        false
+    }
+}
+
+// A path from the camera's lens to some point in the scene
+pub struct LensSample {
+    pub origin: Point3,
+    // image coordinates, as given to CameraModel::ray
+    pub s: f64,
+    pub t: f64,
+    // the camera's importance for this ray: how much it adds to the image
+    pub importance: f64,
+    // density of picking this point on the lens, per solid angle as seen
+    // from the scene
+    pub pdf: f64,
+}
+
+// Available camera models, for picking one at run time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // thin lens
+    Perspective,
+    // parallel rays; the view's vfov and distance to lookat set how much of
+    // the scene fits in the frame
+    Orthographic,
+    // circular fisheye with vfov across the image circle, which fills the
+    // height of the image. Equidistant maps angle linearly to radius,
+    // equisolid preserves area.
+    Fisheye { equisolid: bool },
+    // full sphere: longitude across, latitude up. Wants a 2:1 image.
+    Equirectangular,
+    // six 90 degree faces in a 3x2 grid: +x -x +y on top, -y +z -z below,
+    // along the world axes. Wants a 3:2 image.
+    Cubemap,
+}
+
+impl Projection {
+    // Only the perspective camera has a lens; the others are pinholes
+    #[must_use]
+    pub fn camera(&self, view: &CameraView, vup: Vec3, aspect_ratio: f64,
+                  lens: &Lens, shutter: &Shutter)
+                  -> Arc<dyn CameraModel + Sync + Send> {
+        let frame = CameraFrame::new(view, vup, shutter);
+        match *self {
+            Projection::Perspective => Camera::with_lens(
+                view, vup, aspect_ratio, lens, shutter
+            ).into(),
+            Projection::Orthographic => Orthographic::new(frame, view, aspect_ratio).into(),
+            Projection::Fisheye { equisolid } => Fisheye {
+                frame,
+                aspect_ratio,
+                half_fov: util::degrees_to_radians(0.5 * view.vfov),
+                equisolid,
+            }.into(),
+            Projection::Equirectangular => Equirectangular { frame, eye_offset: 0.0 }.into(),
+            Projection::Cubemap => Cubemap { frame }.into(),
+        }
+    }
+}
+
+// Position, orientation and shutter shared by the camera models
+#[derive(Clone)]
+struct CameraFrame {
+    origin: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    shutter: Shutter,
+}
+
+impl CameraFrame {
+    fn new(view: &CameraView, vup: Vec3, shutter: &Shutter) -> Self {
+        let w = (view.lookfrom - view.lookat).unit_vector();
+        let u = vec3::cross(vup, w).unit_vector();
+        let v = vec3::cross(w, u);
+        Self { origin: view.lookfrom, u, v, w, shutter: shutter.clone() }
+    }
+
+    // Camera space direction (x right, y up, z forward) to world space
+    fn to_world(&self, d: Vec3) -> Vec3 {
+        d.x() * self.u + d.y() * self.v - d.z() * self.w
+    }
+
+    // t is the height in the image, for rolling shutters
+    fn ray(&self, origin: Point3, dir: Vec3, t: f64) -> Ray {
+        Ray {
+            origin,
+            dir,
+            time: self.shutter.time(t),
+            wavelengths: None,
+        }
+    }
+}
+
+impl From<Camera> for Arc<dyn CameraModel + Sync + Send> {
+    fn from(cc: Camera) -> Arc<dyn CameraModel + Sync + Send> {
+        Arc::new(cc)
+    }
+}
+
+impl CameraModel for Camera {
+    fn ray(&self, s: f64, t: f64) -> Option<Ray> {
+        self.get_ray(s, t)
+    }
+
+    fn sample_lens(&self, p: Point3) -> Option<LensSample> {
+        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+        let origin = self.origin + self.u * rd.x() + self.v * rd.y();
+        let to_p = p - origin;
+        let dist = to_p.len();
+        let dir = to_p / dist;
+        let cos_theta = -vec3::dot(dir, self.w);
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let (s, t) = self.raster(origin, dir)?;
+        Some(LensSample {
+            origin, s, t,
+            importance: 1.0 / (self.image_area() * self.lens_area() * cos_theta.powi(4)),
+            pdf: dist * dist / (cos_theta * self.lens_area()),
+        })
+    }
+
+    fn pdf_dir(&self, dir: Vec3) -> f64 {
+        let dir = dir.unit_vector();
+        let cos_theta = -vec3::dot(dir, self.w);
+        if cos_theta <= 0.0 || self.raster(self.origin, dir).is_none() {
+            return 0.0;
+        }
+        1.0 / (self.image_area() * cos_theta.powi(3))
+    }
+
+    // sample_lens() picks points on a plain disk, at the time of the camera
+    // ray it's joined to, and its importance is for a plane of focus parallel
+    // to the lens, so shaped, vignetted, tilted or swung lenses and rolling
+    // shutters are left to the camera's own rays
+    fn has_importance(&self) -> bool {
+        let plain = self.lens_radius <= 0.0
+            || (matches!(self.shape, Aperture::Disk) && self.cat_eye <= 0.0
+                && (self.focus_normal - self.w).near_zero());
+        plain && self.shutter.rolling <= 0.0
+    }
+}
+
+pub struct Orthographic {
+    frame: CameraFrame,
+    width: f64,
+    height: f64,
+}
+
+impl Orthographic {
+    fn new(frame: CameraFrame, view: &CameraView, aspect_ratio: f64) -> Self {
+        // frame what a perspective camera would see at lookat
+        let dist = (view.lookfrom - view.lookat).len();
+        let height = 2.0 * dist * (util::degrees_to_radians(view.vfov) / 2.0).tan();
+        Self { frame, width: aspect_ratio * height, height }
+    }
+}
+
+impl From<Orthographic> for Arc<dyn CameraModel + Sync + Send> {
+    fn from(cc: Orthographic) -> Arc<dyn CameraModel + Sync + Send> {
+        Arc::new(cc)
+    }
+}
+
+impl CameraModel for Orthographic {
+    fn ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let f = &self.frame;
+        let origin = f.origin
+            + (s - 0.5) * self.width * f.u
+            + (t - 0.5) * self.height * f.v;
+        Some(f.ray(origin, -f.w, t))
+    }
+}
+
+pub struct Fisheye {
+    frame: CameraFrame,
+    aspect_ratio: f64,
+    half_fov: f64,
+    equisolid: bool,
+}
+
+impl From<Fisheye> for Arc<dyn CameraModel + Sync + Send> {
+    fn from(cc: Fisheye) -> Arc<dyn CameraModel + Sync + Send> {
+        Arc::new(cc)
+    }
+}
+
+impl CameraModel for Fisheye {
+    fn ray(&self, s: f64, t: f64) -> Option<Ray> {
+        // radius 1 at the edge of the image circle
+        let x = 2.0 * (s - 0.5) * self.aspect_ratio;
+        let y = 2.0 * (t - 0.5);
+        let r = f64::sqrt(x * x + y * y);
+        if r > 1.0 {
+            return None;
+        }
+        let theta = if self.equisolid {
+            2.0 * f64::asin(r * f64::sin(0.5 * self.half_fov))
+        } else {
+            r * self.half_fov
+        };
+        let phi = f64::atan2(y, x);
+        let d = Vec3(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        let f = &self.frame;
+        Some(f.ray(f.origin, f.to_world(d), t))
+    }
+}
+
+pub struct Equirectangular {
+    frame: CameraFrame,
+    // For omni-directional stereo (ODS): each ray starts on a circle of this
+    // radius, from where an eye would be when looking that way. Negative
+    // for the left eye, 0 for an ordinary panorama.
+    eye_offset: f64,
+}
+
+impl From<Equirectangular> for Arc<dyn CameraModel + Sync + Send> {
+    fn from(cc: Equirectangular) -> Arc<dyn CameraModel + Sync + Send> {
+        Arc::new(cc)
+    }
+}
+
+impl CameraModel for Equirectangular {
+    fn ray(&self, s: f64, t: f64) -> Option<Ray> {
+        // straight ahead in the middle of the image
+        let lon = (s - 0.5) * 2.0 * util::PI;
+        let lat = (t - 0.5) * util::PI;
+        let d = Vec3(
+            lat.cos() * lon.sin(),
+            lat.sin(),
+            lat.cos() * lon.cos(),
+        );
+        // the eyes come together toward the poles, where there's no telling
+        // which way is sideways
+        let eye = self.eye_offset * lat.cos() * Vec3(lon.cos(), 0.0, -lon.sin());
+        let f = &self.frame;
+        Some(f.ray(f.origin + f.to_world(eye), f.to_world(d), t))
+    }
+}
+
+pub struct Cubemap {
+    frame: CameraFrame,
+}
+
+impl Cubemap {
+    // (forward, up) for each face, in layout order
+    const FACES : [(Vec3, Vec3); 6] = [
+        (Vec3(1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0)),
+        (Vec3(-1.0, 0.0, 0.0), Vec3(0.0, 1.0, 0.0)),
+        (Vec3(0.0, 1.0, 0.0), Vec3(0.0, 0.0, -1.0)),
+        (Vec3(0.0, -1.0, 0.0), Vec3(0.0, 0.0, 1.0)),
+        (Vec3(0.0, 0.0, 1.0), Vec3(0.0, 1.0, 0.0)),
+        (Vec3(0.0, 0.0, -1.0), Vec3(0.0, 1.0, 0.0)),
+    ];
+}
+
+impl From<Cubemap> for Arc<dyn CameraModel + Sync + Send> {
+    fn from(cc: Cubemap) -> Arc<dyn CameraModel + Sync + Send> {
+        Arc::new(cc)
+    }
+}
+
+impl CameraModel for Cubemap {
+    fn ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let col = ((s * 3.0) as usize).min(2);
+        let row = if t >= 0.5 { 0 } else { 1 };
+        let (forward, up) = Self::FACES[3 * row + col];
+        // right, as a camera looking forward with this up would have it
+        let right = vec3::cross(forward, up);
+
+        // position within the face, in [-1,1]
+        let a = 2.0 * (s * 3.0 - col as f64) - 1.0;
+        let b = 2.0 * (t * 2.0 - (1 - row) as f64) - 1.0;
+        let f = &self.frame;
+        Some(f.ray(f.origin, forward + a * right + b * up, t))
+    }
+}
+
+// Shape of the lens opening, which is what out of focus highlights (bokeh)
+// take the shape of
+#[derive(Clone)]
+pub enum Aperture {
+    Disk,
+    // regular polygon, as made by a diaphragm with this many straight blades,
+    // rotated by some angle (degrees)
+    Polygon { blades: u32, rotation: f64 },
+    // arbitrary shape from an image: brighter is more open
+    Image(Arc<ApertureImage>),
+}
+
+impl Aperture {
+    // Point on the aperture, within the square from (-1,-1) to (1,1). The
+    // disk and polygons are inscribed in it; an image fills it.
+    #[must_use]
+    pub fn sample(&self) -> Vec3 {
+        match self {
+            Aperture::Disk => Vec3::random_in_unit_disk(),
+            Aperture::Polygon { blades, rotation } => {
+                // pick a triangle of the fan, then a point in it
+                let n = (*blades).max(3);
+                let k = random::uint(0, n as usize - 1) as f64;
+                let step = 2.0 * util::PI / f64::from(n);
+                let a0 = util::degrees_to_radians(*rotation) + k * step;
+                let p1 = Vec3(a0.cos(), a0.sin(), 0.0);
+                let p2 = Vec3((a0 + step).cos(), (a0 + step).sin(), 0.0);
+                let (mut x, mut y) = (random::double(), random::double());
+                if x + y > 1.0 {
+                    x = 1.0 - x;
+                    y = 1.0 - y;
+                }
+                x * p1 + y * p2
+            },
+            Aperture::Image(img) => img.sample(),
+        }
+    }
+}
+
+// Aperture mask, sampled in proportion to how bright each pixel is
+pub struct ApertureImage {
+    width: usize,
+    height: usize,
+    // running sum of the pixel weights
+    cdf: Vec<f64>,
+}
+
+impl ApertureImage {
+    /// # Panics
+    ///
+    /// Panics if the file can't be read, or is completely black
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(fname: P) -> Self {
+        let img = image::open(fname.as_ref()).expect("File not found!").to_luma32f();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut total = 0.0;
+        let cdf: Vec<f64> = img.pixels().map(|p| {
+            total += f64::from(p.0[0]).max(0.0);
+            total
+        }).collect();
+        assert!(total > 0.0, "{}: aperture is closed", fname.as_ref().display());
+        Self { width, height, cdf }
+    }
+
+    fn sample(&self) -> Vec3 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = random::double() * total;
+        let i = self.cdf.partition_point(|c| *c <= target).min(self.cdf.len() - 1);
+        let (x, y) = ((i % self.width) as f64, (i / self.width) as f64);
+        // fit the image in the unit square, image rows going down
+        let size = self.width.max(self.height) as f64;
+        Vec3(
+            2.0 * (x + random::double()) / size - 1.0,
+            1.0 - 2.0 * (y + random::double()) / size,
+            0.0,
+        )
+    }
+}
+
+impl From<ApertureImage> for Aperture {
+    fn from(img: ApertureImage) -> Aperture {
+        Aperture::Image(Arc::new(img))
+    }
+}
+
+// Everything about the lens other than where it's pointed
+#[derive(Clone)]
+pub struct Lens {
+    // diameter of the opening
+    pub aperture: f64,
+    pub shape: Aperture,
+    // Optical vignetting: the lens barrel cuts off part of the aperture away
+    // from the center of the image, giving "cat's eye" bokeh and darker
+    // corners. 0 is off; at 1 the aperture is cut in half at the corners.
+    pub cat_eye: f64,
+    // Tilt (about the horizontal) and swing (about the vertical) of the
+    // lens, in degrees, which tilts the plane of focus (Scheimpflug).
+    // Positive tilt brings the top of the image nearer, positive swing the
+    // left side.
+    pub tilt: f64,
+    pub swing: f64,
+    // Shift of the lens across the image plane, as a fraction of the image's
+    // width and height
+    pub shift: (f64, f64),
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Self {
+            aperture: 0.0,
+            shape: Aperture::Disk,
+            cat_eye: 0.0,
+            tilt: 0.0,
+            swing: 0.0,
+            shift: (0.0, 0.0),
+        }
+    }
+}
 
 pub struct Camera {
     origin : Point3,
@@ -17,8 +491,12 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
-    time0 : f64,
-    time1 : f64,
+    shape: Aperture,
+    cat_eye: f64,
+    // the plane of focus: a point on it, and its normal
+    focus_point: Point3,
+    focus_normal: Vec3,
+    shutter: Shutter,
 }
 
 
@@ -33,9 +511,35 @@ impl Camera {
                time0: f64,
                time1: f64,
     ) -> Camera {
-        let lookfrom = scene.lookfrom;
-        let lookat = scene.lookat;
-        let vfov = scene.vfov;
+        Self::from_view(
+            &CameraView::from_scene(scene, focus_dist),
+            vup, aspect_ratio, aperture, time0, time1,
+        )
+    }
+
+    #[must_use]
+    pub fn from_view(view: &CameraView,
+                     vup: Vec3,
+                     aspect_ratio: f64,
+                     aperture: f64,
+                     time0: f64,
+                     time1: f64,
+    ) -> Camera {
+        Self::with_lens(view, vup, aspect_ratio, &Lens { aperture, ..Lens::default() },
+                        &Shutter::new(time0, time1))
+    }
+
+    #[must_use]
+    pub fn with_lens(view: &CameraView,
+                     vup: Vec3,
+                     aspect_ratio: f64,
+                     lens: &Lens,
+                     shutter: &Shutter,
+    ) -> Camera {
+        let lookfrom = view.lookfrom;
+        let lookat = view.lookat;
+        let vfov = view.vfov;
+        let focus_dist = view.focus_dist;
         let theta = util::degrees_to_radians(vfov);
         let h = (theta / 2.).tan();
 
@@ -61,32 +565,202 @@ impl Camera {
         // Actual dimensions of the focus plane
         let horizontal = focus_dist * view_width * u;
         let vertical = focus_dist * view_height * v;
-        let lower_left = origin - horizontal / 2 - vertical / 2 - focus_dist * w;
+        let lower_left = origin - horizontal / 2 - vertical / 2 - focus_dist * w
+            + lens.shift.0 * horizontal + lens.shift.1 * vertical;
+
+        // Untilted, the plane of focus is parallel to the lens. Tilting
+        // swings it about where it crosses the view axis.
+        let tilt = Mat4::rotate(u, lens.tilt) * Mat4::rotate(v, lens.swing);
+        let focus_normal = tilt.vector(w);
+        let focus_point = origin - focus_dist * w;
 
-        let lens_radius = aperture / 2.;
+        let lens_radius = lens.aperture / 2.;
         Camera {
             origin,
             lower_left,
             horizontal, vertical,
             u, v, w,
             lens_radius,
-            time0, time1,
+            shape: lens.shape.clone(),
+            cat_eye: lens.cat_eye,
+            focus_point, focus_normal,
+            shutter: shutter.clone(),
+        }
+    }
+
+    // Where a ray leaving the lens at origin in direction dir shows up on
+    // the image, i.e. get_ray() backwards. None if it's out of frame.
+    fn raster(&self, origin: Point3, dir: Vec3) -> Option<(f64, f64)> {
+        let along = vec3::dot(dir, self.focus_normal);
+        if along.abs() < 1e-12 {
+            return None;
+        }
+        let dist = vec3::dot(self.focus_point - origin, self.focus_normal) / along;
+        if dist <= 0.0 {
+            return None;
+        }
+        // back through the center of the lens to the image
+        let pinhole = origin + dist * dir - self.origin;
+        let depth = -vec3::dot(pinhole, self.w);
+        if depth <= 0.0 {
+            return None;
+        }
+        let q = self.origin + pinhole * (self.focus_dist() / depth) - self.lower_left;
+        let s = vec3::dot(q, self.horizontal) / self.horizontal.len_squared();
+        let t = vec3::dot(q, self.vertical) / self.vertical.len_squared();
+        ((0.0..=1.0).contains(&s) && (0.0..=1.0).contains(&t)).then_some((s, t))
+    }
+
+    fn focus_dist(&self) -> f64 {
+        vec3::dot(self.origin - self.focus_point, self.w)
+    }
+
+    // Area of the image, moved to 1 unit from the lens
+    fn image_area(&self) -> f64 {
+        let fd = self.focus_dist();
+        self.horizontal.len() * self.vertical.len() / (fd * fd)
+    }
+
+    // A pinhole counts as 1, so its importance is just per solid angle
+    fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 {
+            util::PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
         }
     }
 
+    // None if the lens barrel blocks the ray (see Lens::cat_eye)
     #[must_use]
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    pub fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let rd = self.shape.sample();
+        if self.cat_eye > 0.0 {
+            // the barrel's opening, seen from this pixel, is a disk the size
+            // of the aperture shifted toward the edge of the image
+            let shift = Vec3(2.0 * s - 1.0, 2.0 * t - 1.0, 0.0) * self.cat_eye
+                / f64::sqrt(2.0);
+            if (rd - shift).len_squared() > 1.0 {
+                return None;
+            }
+        }
+        let rd = self.lens_radius * rd;
         let offset = self.u * rd.x() + self.v * rd.y();
+
+        // where the ray through the center of the lens meets the plane of
+        // focus; every ray through the lens for this pixel goes there too
+        let pinhole = self.lower_left + (s * self.horizontal) + (t * self.vertical)
+            - self.origin;
+        let dist = vec3::dot(self.focus_point - self.origin, self.focus_normal)
+            / vec3::dot(pinhole, self.focus_normal);
+        let target = self.origin + dist * pinhole;
+
         // generate randomly timed rays out into the scene
-        Ray {
+        Some(Ray {
             origin: self.origin + offset,
-            dir: self.lower_left +
-                (s * self.horizontal) +
-                (t * self.vertical) -
-                self.origin - offset,
-            time: random::double_range(self.time0, self.time1),
-        }
+            dir: target - self.origin - offset,
+            time: self.shutter.time(t),
+            wavelengths: None,
+        })
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StereoLayout {
+    // left eye on the left half of the image
+    SideBySide,
+    // left eye on the top half
+    TopBottom,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Convergence {
+    // Eyes look the same way, with their images shifted so that things at
+    // the focus distance line up (zero parallax)
+    Parallel,
+    // Eyes turn in to meet at the focus distance. Simpler, but gives some
+    // vertical disparity toward the corners.
+    ToeIn,
+}
+
+// Two eyes, interocular distance apart along the camera's u, rendered into
+// one image
+#[derive(Copy, Clone)]
+pub struct StereoRig {
+    pub interocular: f64,
+    pub convergence: Convergence,
+    pub layout: StereoLayout,
+}
+
+impl StereoRig {
+    // Equirectangular projections become omni-directional stereo panoramas;
+    // everything else gets an eye on either side of the view.
+    // Each eye has its own rolling shutter, as with a rig of two cameras.
+    #[must_use]
+    pub fn camera(&self, projection: Projection, view: &CameraView, vup: Vec3,
+                  aspect_ratio: f64, lens: &Lens, shutter: &Shutter)
+                  -> Arc<dyn CameraModel + Sync + Send> {
+        let eye_aspect = match self.layout {
+            StereoLayout::SideBySide => aspect_ratio / 2.0,
+            StereoLayout::TopBottom => aspect_ratio * 2.0,
+        };
+        let half = 0.5 * self.interocular;
+
+        let eye = |side: f64| -> Arc<dyn CameraModel + Sync + Send> {
+            let frame = CameraFrame::new(view, vup, shutter);
+            if projection == Projection::Equirectangular {
+                return Equirectangular { frame, eye_offset: side * half }.into();
+            }
+
+            let offset = side * half * frame.u;
+            let lookfrom = view.lookfrom + offset;
+            let lookat = match self.convergence {
+                Convergence::Parallel => view.lookat + offset,
+                Convergence::ToeIn => view.lookfrom - view.focus_dist * frame.w,
+            };
+            let eye_view = CameraView { lookfrom, lookat, ..*view };
+
+            // move each eye's image back over the middle at the focus distance
+            let lens = match (self.convergence, projection) {
+                (Convergence::Parallel, Projection::Perspective) => {
+                    let width = 2.0 * view.focus_dist
+                        * (util::degrees_to_radians(view.vfov) / 2.0).tan() * eye_aspect;
+                    Lens {
+                        shift: (lens.shift.0 - side * half / width, lens.shift.1),
+                        ..lens.clone()
+                    }
+                },
+                _ => lens.clone(),
+            };
+            projection.camera(&eye_view, vup, eye_aspect, &lens, shutter)
+        };
+
+        Stereo {
+            left: eye(-1.0),
+            right: eye(1.0),
+            layout: self.layout,
+        }.into()
+    }
+}
+
+pub struct Stereo {
+    left: Arc<dyn CameraModel + Sync + Send>,
+    right: Arc<dyn CameraModel + Sync + Send>,
+    layout: StereoLayout,
+}
+
+impl From<Stereo> for Arc<dyn CameraModel + Sync + Send> {
+    fn from(cc: Stereo) -> Arc<dyn CameraModel + Sync + Send> {
+        Arc::new(cc)
+    }
+}
+
+impl CameraModel for Stereo {
+    fn ray(&self, s: f64, t: f64) -> Option<Ray> {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => self.left.ray(2.0 * s, t),
+            StereoLayout::SideBySide => self.right.ray(2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => self.left.ray(s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => self.right.ray(s, 2.0 * t),
+        }
+    }
+}
@@ -35,6 +35,16 @@ impl ConstantMedium {
             phase_fn: Isotropic::from_texture(a).into(),
         }
     }
+
+    #[must_use]
+    pub fn with_phase(boundary: Arc<dyn Hittable + Sync + Send>, d: f64,
+                      phase_fn: Arc<dyn Material + Sync + Send>) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / d,
+            phase_fn,
+        }
+    }
 }
 
 impl From<ConstantMedium> for Arc<dyn Hittable + Sync + Send> {
@@ -95,10 +105,34 @@ impl Hittable for ConstantMedium {
 
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.phase_fn);
+    }
+
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
         self.boundary.bounding_box(time0, time1)
     }
 
+    // Density is constant, so Beer-Lambert gives the transmittance over the
+    // overlap between [t_min, t_max] and the boundary directly.
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let Some(hr1) = self.boundary.hit(r, NEG_INFINITY, INFINITY) else {
+            return 1.0;
+        };
+        let Some(hr2) = self.boundary.hit(r, hr1.t + 0.0001, INFINITY) else {
+            return 1.0;
+        };
+
+        let t0 = f64::max(f64::max(hr1.t, t_min), 0.0);
+        let t1 = f64::min(hr2.t, t_max);
+
+        if t0 >= t1 {
+            return 1.0;
+        }
+
+        f64::exp((t1 - t0) * r.dir.len() / self.neg_inv_density)
+    }
+
     // fn pdf_value(&self, origin: &Point3, v: &Vec3) -> f64 {
 
     // }
@@ -0,0 +1,276 @@
+use crate::vec3::{Vec3,Point3,dot,cross};
+use crate::ray::Ray;
+use crate::hit::{HitRecord,Hittable};
+use crate::material::Material;
+use crate::aabb::AABB;
+use crate::hittable_list::HittableList;
+use crate::bvh::BVHNode;
+
+use std::sync::Arc;
+use std::path::Path;
+
+// Straight piece of a curve, rendered as a flat ribbon that always faces the
+// incoming ray (like pbrt's "flat" curves). We hand back the normal of the
+// round fiber it stands in for, so fiber BSDFs can tell where across the
+// width they were hit.
+pub struct CurveSegment {
+    p0: Point3,
+    p1: Point3,
+    r0: f64,
+    r1: f64,
+    // parameter range along the whole curve
+    u0: f64,
+    u1: f64,
+    mat: Arc<dyn Material + Sync + Send>,
+}
+
+impl CurveSegment {
+    #[must_use]
+    pub fn new(p0: Point3, p1: Point3, r0: f64, r1: f64, u0: f64, u1: f64,
+               mat: Arc<dyn Material + Sync + Send>) -> Self {
+        Self {
+            p0, p1, r0, r1, u0, u1, mat,
+        }
+    }
+
+    // Parameter along the segment of the point nearest p, clamped to [0,1]
+    fn nearest(&self, p: Point3) -> f64 {
+        let e = self.p1 - self.p0;
+        (dot(p - self.p0, e) / dot(e, e)).clamp(0.0, 1.0)
+    }
+
+    fn radius(&self, s: f64) -> f64 {
+        (1.0 - s) * self.r0 + s * self.r1
+    }
+}
+
+impl From<CurveSegment> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: CurveSegment) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for CurveSegment {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // A ray leaving from the fiber itself (e.g. after transmission)
+        // shouldn't find it again
+        let s_origin = self.nearest(r.origin);
+        let p_origin = self.p0 + s_origin * (self.p1 - self.p0);
+        if (r.origin - p_origin).len() <= 1.01 * self.radius(s_origin) {
+            return None;
+        }
+
+        // Closest approach between the ray and the segment's axis
+        let e = self.p1 - self.p0;
+        let w = r.origin - self.p0;
+        let a = dot(r.dir, r.dir);
+        let b = dot(r.dir, e);
+        let c = dot(e, e);
+        let d = dot(r.dir, w);
+        let f = dot(e, w);
+        let denom = a * c - b * b;
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = (b * f - c * d) / denom;
+        let s = (a * f - b * d) / denom;
+        if !(0.0..=1.0).contains(&s) || t < t_min || t > t_max {
+            return None;
+        }
+
+        let p = r.at(t);
+        let offset = p - (self.p0 + s * e);
+        let radius = self.radius(s);
+        if offset.len() > radius {
+            return None;
+        }
+
+        // Frame: tangent, "up" facing the ray, and across the ribbon
+        let tangent = e.unit_vector();
+        let facing = -(r.dir - dot(r.dir, tangent) * tangent).unit_vector();
+        let across = cross(facing, tangent);
+        let h = (dot(offset, across) / radius).clamp(-1.0, 1.0);
+        let norm = h * across + f64::sqrt(1.0 - h * h) * facing;
+
+        Some(HitRecord::with_dps(
+            r, p, norm, t,
+            self.u0 + s * (self.u1 - self.u0), 0.5 * (h + 1.0),
+            self.mat.clone(),
+            tangent, across,
+        ))
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mat);
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        let r = f64::max(self.r0, self.r1);
+        let pad = Vec3(r, r, r);
+        Some(AABB {
+            min: Point3(
+                f64::min(self.p0.x(), self.p1.x()),
+                f64::min(self.p0.y(), self.p1.y()),
+                f64::min(self.p0.z(), self.p1.z()),
+            ) - pad,
+            max: Point3(
+                f64::max(self.p0.x(), self.p1.x()),
+                f64::max(self.p0.y(), self.p1.y()),
+                f64::max(self.p0.z(), self.p1.z()),
+            ) + pad,
+        })
+    }
+}
+
+// Cubic Bézier curve with width tapering linearly from root to tip, diced
+// into straight segments.
+pub struct Curve {
+    segments: BVHNode,
+}
+
+impl Curve {
+    pub const DEFAULT_SEGMENTS : usize = 8;
+
+    #[must_use]
+    pub fn new(cp: [Point3; 4], width0: f64, width1: f64, n_segments: usize,
+               mat: Arc<dyn Material + Sync + Send>) -> Self {
+        Self {
+            segments: BVHNode::new(
+                &Self::dice(cp, width0, width1, n_segments, &mat), 0.0, 1.0
+            ),
+        }
+    }
+
+    #[must_use]
+    pub fn eval(cp: &[Point3; 4], u: f64) -> Point3 {
+        let v = 1.0 - u;
+        v * v * v * cp[0]
+            + 3.0 * v * v * u * cp[1]
+            + 3.0 * v * u * u * cp[2]
+            + u * u * u * cp[3]
+    }
+
+    fn dice(cp: [Point3; 4], width0: f64, width1: f64, n_segments: usize,
+            mat: &Arc<dyn Material + Sync + Send>) -> HittableList {
+        let n = n_segments.max(1);
+        let width = |u: f64| (1.0 - u) * width0 + u * width1;
+        HittableList::new((0..n).map(|i| {
+            let u0 = i as f64 / n as f64;
+            let u1 = (i + 1) as f64 / n as f64;
+            CurveSegment::new(
+                Self::eval(&cp, u0), Self::eval(&cp, u1),
+                0.5 * width(u0), 0.5 * width(u1),
+                u0, u1, mat.clone(),
+            ).into()
+        }).collect())
+    }
+}
+
+impl From<Curve> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: Curve) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for Curve {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.segments.hit(r, t_min, t_max)
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.segments.materials(f);
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.segments.bounding_box(time0, time1)
+    }
+}
+
+// A bunch of strands (hair, fur, grass) loaded from a simple text format: one
+// strand per line, as
+//
+//   width_root width_tip x0 y0 z0 x1 y1 z1 ...
+//
+// where the points are the control points of consecutive cubic Béziers
+// sharing end points, i.e. 3k + 1 of them. Blank lines and lines starting
+// with '#' are skipped.
+pub struct Strands {
+    curves: BVHNode,
+    pub n_strands: usize,
+}
+
+impl Strands {
+    /// # Panics
+    ///
+    /// Panics if the file can't be read, or a line isn't a valid strand
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(fname: P, scale: f64,
+                               mat: Arc<dyn Material + Sync + Send>) -> Self {
+        let text = std::fs::read_to_string(fname.as_ref()).expect("File not found!");
+        let strands: Vec<(f64, f64, Vec<Point3>)> = text.lines().enumerate()
+            .filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+            .map(|(i, l)| {
+                let vals: Vec<f64> = l.split_whitespace().map(|v| {
+                    v.parse().unwrap_or_else(|_| {
+                        panic!("{}:{}: bad number '{}'", fname.as_ref().display(), i + 1, v)
+                    })
+                }).collect();
+                assert!(vals.len() >= 14 && (vals.len() - 2).is_multiple_of(3)
+                        && ((vals.len() - 2) / 3 - 1).is_multiple_of(3),
+                        "{}:{}: expected widths and 3k + 1 control points",
+                        fname.as_ref().display(), i + 1);
+                let points = vals[2..].chunks(3).map(|p| {
+                    scale * Point3(p[0], p[1], p[2])
+                }).collect();
+                (scale * vals[0], scale * vals[1], points)
+            }).collect();
+
+        let result = Self::from_strands(&strands, mat);
+        eprintln!("{}: strands: {}", fname.as_ref().display(), result.n_strands);
+        result
+    }
+
+    // Each strand is (root width, tip width, control points)
+    #[must_use]
+    pub fn from_strands(strands: &[(f64, f64, Vec<Point3>)],
+                        mat: Arc<dyn Material + Sync + Send>) -> Self {
+        let mut curves = HittableList::default();
+        for (w0, w1, points) in strands {
+            let n_curves = (points.len() - 1) / 3;
+            for k in 0..n_curves {
+                let width = |u: f64| w0 + (w1 - w0) * u;
+                let cp = [points[3 * k], points[3 * k + 1], points[3 * k + 2], points[3 * k + 3]];
+                curves.add(Curve::new(
+                    cp,
+                    width(k as f64 / n_curves as f64),
+                    width((k + 1) as f64 / n_curves as f64),
+                    Curve::DEFAULT_SEGMENTS, mat.clone(),
+                ).into());
+            }
+        }
+        Self {
+            curves: BVHNode::new(&curves, 0.0, 1.0),
+            n_strands: strands.len(),
+        }
+    }
+}
+
+impl From<Strands> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: Strands) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for Strands {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.curves.hit(r, t_min, t_max)
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.curves.materials(f);
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.curves.bounding_box(time0, time1)
+    }
+}
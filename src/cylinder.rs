@@ -91,6 +91,10 @@ impl Hittable for Cylinder {
 
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mat);
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         Some(AABB {
             min: Point3(-self.radius, self.y_min, -self.radius),
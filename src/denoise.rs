@@ -0,0 +1,172 @@
+use crate::film::Film;
+use crate::vec3::{self, Vec3, Color};
+
+use rayon::prelude::*;
+
+// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010), guided by
+// what the camera rays hit first: albedo, normal and depth, as in SVGF. The
+// lighting is filtered with the albedo divided out, so textures stay sharp.
+#[derive(Copy, Clone)]
+pub struct Denoiser {
+    // Passes of the filter. Each one reaches twice as far as the last, so 5
+    // covers a 125 pixel square.
+    pub iterations: u32,
+    // How different two (tone mapped) colors can be and still get blended
+    pub sigma_color: f64,
+    // Power of the cosine between normals; higher keeps creases sharper
+    pub normal_power: f64,
+    // How far apart in depth neighbors can be, relative to how fast the
+    // depth is changing there
+    pub sigma_depth: f64,
+    pub sigma_albedo: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.5,
+            normal_power: 64.0,
+            sigma_depth: 1.0,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+// Feature buffers for the filter's edge stopping
+struct Guide {
+    width: usize,
+    height: usize,
+    albedo: Vec<Color>,
+    normal: Vec<Vec3>,
+    depth: Vec<f64>,
+    // screen space depth gradient, per pixel in x and y
+    depth_grad: Vec<(f64, f64)>,
+}
+
+impl Guide {
+    fn new(film: &Film) -> Option<Self> {
+        let (width, height) = (film.width(), film.height());
+        let albedo = film.pixels("albedo", &["R", "G", "B"])?;
+        // the film's normals are averaged over the pixel, so not quite unit
+        let normal = film.pixels("normal", &["X", "Y", "Z"])?.iter().map(|n| {
+            if n.near_zero() { *n } else { n.unit_vector() }
+        }).collect();
+        let depth: Vec<f64> = film.pixels("depth", &["Z"])?.iter().map(Vec3::x).collect();
+
+        let diff = |a: usize, b: usize, dist: f64| {
+            if depth[a].is_finite() && depth[b].is_finite() {
+                (depth[b] - depth[a]) / dist
+            } else {
+                0.0
+            }
+        };
+        let depth_grad = (0..width * height).map(|p| {
+            let (x, y) = (p % width, p / width);
+            let (left, right) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (up, down) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            #[allow(clippy::cast_precision_loss)]
+            (
+                diff(y * width + left, y * width + right, (right - left).max(1) as f64),
+                diff(up * width + x, down * width + x, (down - up).max(1) as f64),
+            )
+        }).collect();
+
+        Some(Self { width, height, albedo, normal, depth, depth_grad })
+    }
+
+    // How much pixel q should count toward filtering pixel p, from the
+    // features alone. (dx, dy) is q's offset from p.
+    #[allow(clippy::cast_precision_loss)]
+    fn weight(&self, dn: &Denoiser, p: usize, q: usize, dx: isize, dy: isize) -> f64 {
+        let (zp, zq) = (self.depth[p], self.depth[q]);
+        let w_depth = match (zp.is_finite(), zq.is_finite()) {
+            (true, true) => {
+                let (gx, gy) = self.depth_grad[p];
+                let expected = (gx * dx as f64 + gy * dy as f64).abs() + 1e-3 * zp;
+                (-(zp - zq).abs() / (dn.sigma_depth * expected)).exp()
+            },
+            // both background
+            (false, false) => 1.0,
+            _ => 0.0,
+        };
+
+        let (np, nq) = (self.normal[p], self.normal[q]);
+        let w_normal = if np.near_zero() || nq.near_zero() {
+            1.0
+        } else {
+            vec3::dot(np, nq).max(0.0).powf(dn.normal_power)
+        };
+
+        let w_albedo = (-(self.albedo[p] - self.albedo[q]).len_squared()
+                        / (dn.sigma_albedo * dn.sigma_albedo)).exp();
+
+        w_depth * w_normal * w_albedo
+    }
+}
+
+impl Denoiser {
+    // B3 spline, by distance from the center
+    const KERNEL : [f64; 3] = [3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+    // The named RGB layer of the film, denoised. None if the film is missing
+    // it or any of the albedo, normal and depth layers.
+    #[must_use]
+    pub fn denoise(&self, film: &Film, layer: &str) -> Option<Vec<Color>> {
+        let guide = Guide::new(film)?;
+        let color = film.pixels(layer, &["R", "G", "B"])?;
+
+        // where there's no albedo (background, lights), leave the color be
+        let albedo: Vec<Color> = guide.albedo.iter().map(|a| {
+            Color(demod(a.r()), demod(a.g()), demod(a.b()))
+        }).collect();
+        let mut light: Vec<Color> = color.iter().zip(&albedo).map(|(c, a)| {
+            Color(c.r() / a.r(), c.g() / a.g(), c.b() / a.b())
+        }).collect();
+
+        for i in 0..self.iterations {
+            let step = 1_isize << i;
+            light = (0..light.len()).into_par_iter().map(|p| {
+                self.filter(&guide, &light, p, step)
+            }).collect();
+        }
+
+        Some(light.iter().zip(&albedo).map(|(l, a)| *l * *a).collect())
+    }
+
+    // One pass of the filter at pixel p, with taps step pixels apart
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    fn filter(&self, guide: &Guide, light: &[Color], p: usize, step: isize) -> Color {
+        let (w, h) = (guide.width as isize, guide.height as isize);
+        let (x, y) = ((p % guide.width) as isize, (p / guide.width) as isize);
+        let tp = tone_map(light[p]);
+        let sigma2 = self.sigma_color * self.sigma_color;
+
+        let mut sum = Color(0.0, 0.0, 0.0);
+        let mut total = 0.0;
+        for j in -2_isize..=2 {
+            for i in -2_isize..=2 {
+                let (qx, qy) = (x + i * step, y + j * step);
+                if qx < 0 || qx >= w || qy < 0 || qy >= h {
+                    continue;
+                }
+                let q = (qy * w + qx) as usize;
+                let w_color = (-(tone_map(light[q]) - tp).len_squared() / sigma2).exp();
+                let weight = Self::KERNEL[i.unsigned_abs()] * Self::KERNEL[j.unsigned_abs()]
+                    * w_color * guide.weight(self, p, q, i * step, j * step);
+                sum += weight * light[q];
+                total += weight;
+            }
+        }
+        if total > 0.0 { sum / total } else { light[p] }
+    }
+}
+
+fn demod(a: f64) -> f64 {
+    if a > 1e-3 { a } else { 1.0 }
+}
+
+// Squash bright colors, so a few fireflies don't decide what's an edge
+fn tone_map(c: Color) -> Color {
+    Color(c.r() / (1.0 + c.r()), c.g() / (1.0 + c.g()), c.b() / (1.0 + c.b()))
+}
@@ -66,6 +66,10 @@ impl Hittable for Disk {
         ))
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mat);
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         Some(AABB {
             min: Point3(-self.radius, self.height - 0.000_001, -self.radius),
@@ -0,0 +1,164 @@
+use crate::util::{self, random};
+
+use std::sync::Arc;
+
+// How open the shutter is over the time it's open, which sets how motion
+// blur trails off
+#[derive(Clone)]
+pub enum ShutterCurve {
+    // fully open the whole time (hard edged blur)
+    Box,
+    // opens and closes linearly, fully open half way through
+    Triangle,
+    Custom(Arc<ShutterProfile>),
+}
+
+impl ShutterCurve {
+    // A point in [0,1] within the open shutter, distributed like the curve
+    fn sample(&self) -> f64 {
+        match self {
+            ShutterCurve::Box => random::double(),
+            // the sum of two uniform samples has a triangular distribution
+            ShutterCurve::Triangle => 0.5 * (random::double() + random::double()),
+            ShutterCurve::Custom(profile) => profile.sample(),
+        }
+    }
+
+    // Light let in, relative to a shutter that's fully open the whole time
+    #[must_use]
+    pub fn efficiency(&self) -> f64 {
+        match self {
+            ShutterCurve::Box => 1.0,
+            ShutterCurve::Triangle => 0.5,
+            ShutterCurve::Custom(profile) => profile.efficiency,
+        }
+    }
+}
+
+impl From<ShutterProfile> for ShutterCurve {
+    fn from(profile: ShutterProfile) -> ShutterCurve {
+        ShutterCurve::Custom(Arc::new(profile))
+    }
+}
+
+// Shutter opening given at evenly spaced times from open to close, linear
+// in between. Only the shape matters: the peak is taken as fully open.
+pub struct ShutterProfile {
+    values: Vec<f64>,
+    // running total of the area under each segment
+    cdf: Vec<f64>,
+    efficiency: f64,
+}
+
+impl ShutterProfile {
+    /// # Panics
+    ///
+    /// Panics if there are fewer than two values, any are negative, or
+    /// they're all zero
+    #[must_use]
+    pub fn new(values: &[f64]) -> Self {
+        assert!(values.len() >= 2, "Shutter profile needs at least two values");
+        assert!(values.iter().all(|v| *v >= 0.0), "Shutter profile can't be negative");
+        let peak = values.iter().copied().fold(0.0, f64::max);
+        assert!(peak > 0.0, "Shutter profile is never open");
+
+        let values: Vec<f64> = values.iter().map(|v| v / peak).collect();
+        let mut total = 0.0;
+        let cdf: Vec<f64> = values.windows(2).map(|w| {
+            total += 0.5 * (w[0] + w[1]);
+            total
+        }).collect();
+        let efficiency = total / cdf.len() as f64;
+        Self { values, cdf, efficiency }
+    }
+
+    fn sample(&self) -> f64 {
+        let total = self.cdf[self.cdf.len() - 1];
+        let target = random::double() * total;
+        let i = self.cdf.partition_point(|c| *c < target).min(self.cdf.len() - 1);
+        let before = if i == 0 { 0.0 } else { self.cdf[i - 1] };
+
+        // invert the area under the line from a to b across the segment
+        let (a, b) = (self.values[i], self.values[i + 1]);
+        let area = (target - before).max(0.0);
+        let x = if (b - a).abs() < 1e-8 {
+            if a > 0.0 { area / a } else { random::double() }
+        } else {
+            (-a + (a * a + 2.0 * (b - a) * area).max(0.0).sqrt()) / (b - a)
+        };
+        (i as f64 + x.clamp(0.0, 1.0)) / self.cdf.len() as f64
+    }
+}
+
+// When the camera is taking in light
+#[derive(Clone)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+    pub curve: ShutterCurve,
+    // Rolling shutter: the fraction of the open time spent reading out the
+    // rows, top to bottom. Each row sees the rest of it. 0 is a global
+    // shutter, where all the rows see the same interval.
+    pub rolling: f64,
+}
+
+impl Shutter {
+    // Global box shutter, i.e. uniformly random times between open and close
+    #[must_use]
+    pub fn new(open: f64, close: f64) -> Self {
+        Self { open, close, curve: ShutterCurve::Box, rolling: 0.0 }
+    }
+
+    // Random time for a ray at height t in the image (0 at the bottom)
+    #[must_use]
+    pub fn time(&self, t: f64) -> f64 {
+        let rolling = self.rolling.clamp(0.0, 1.0);
+        let frac = rolling * (1.0 - t.clamp(0.0, 1.0))
+            + (1.0 - rolling) * self.curve.sample();
+        self.open + frac * (self.close - self.open)
+    }
+}
+
+// Photographic exposure settings. The renderer's radiance has no real
+// units, so they're calibrated by the "sunny 16" rule: ISO 100 at 1/100s
+// and f/16 leaves the image as bright as it would be without them.
+#[derive(Copy, Clone)]
+pub struct Exposure {
+    pub iso: f64,
+    // seconds
+    pub shutter_speed: f64,
+    pub f_stop: f64,
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self { iso: 100.0, shutter_speed: 0.01, f_stop: 16.0 }
+    }
+}
+
+impl Exposure {
+    // Full frame (36x24mm) sensor height
+    const SENSOR_HEIGHT : f64 = 0.024;
+
+    // What to multiply the image by. A shutter that isn't fully open the
+    // whole time lets in less light.
+    #[must_use]
+    pub fn scale(&self, curve: &ShutterCurve) -> f64 {
+        (self.iso / 100.0) * (self.shutter_speed / 0.01) * (16.0 / self.f_stop).powi(2)
+            * curve.efficiency()
+    }
+
+    // Focal length (in meters) of a lens on a full frame camera with this
+    // vertical field of view
+    #[must_use]
+    pub fn focal_length(vfov: f64) -> f64 {
+        0.5 * Self::SENSOR_HEIGHT / (util::degrees_to_radians(vfov) / 2.0).tan()
+    }
+
+    // Diameter of the aperture in scene units, for a full frame camera with
+    // this field of view
+    #[must_use]
+    pub fn aperture(&self, vfov: f64, units_per_meter: f64) -> f64 {
+        Self::focal_length(vfov) / self.f_stop * units_per_meter
+    }
+}
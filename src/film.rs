@@ -0,0 +1,164 @@
+use crate::vec3::Vec3;
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes,
+    IntegerBounds, Layer, LayerAttributes, ReadChannels, ReadLayers, SmallVec, Vec2,
+    WritableImage,
+};
+
+use std::path::Path;
+
+// One image in a Film, e.g. the depth or the normals, with a few channels
+pub struct FilmLayer {
+    name: String,
+    channels: Vec<String>,
+    // channel by channel, each in rows from the top
+    data: Vec<f32>,
+}
+
+// Floating point images of a render, each pixel given top to bottom, left to
+// right. Written out as one multi-layer OpenEXR file.
+pub struct Film {
+    width: usize,
+    height: usize,
+    layers: Vec<FilmLayer>,
+}
+
+impl Film {
+    #[must_use]
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, layers: vec![] }
+    }
+
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[must_use]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // A new layer, all zeros, by index
+    pub fn add_layer(&mut self, name: &str, channels: &[&str]) -> usize {
+        self.layers.push(FilmLayer {
+            name: name.to_string(),
+            channels: channels.iter().map(ToString::to_string).collect(),
+            data: vec![0.0; channels.len() * self.width * self.height],
+        });
+        self.layers.len() - 1
+    }
+
+    /// # Panics
+    ///
+    /// Panics if values doesn't have one entry per channel of the layer
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn set(&mut self, layer: usize, x: usize, y: usize, values: &[f64]) {
+        let n_pixels = self.width * self.height;
+        let pixel = y * self.width + x;
+        let layer = &mut self.layers[layer];
+        assert_eq!(values.len(), layer.channels.len(), "Wrong number of channels");
+        for (c, v) in values.iter().enumerate() {
+            layer.data[c * n_pixels + pixel] = *v as f32;
+        }
+    }
+
+    // Up to three of a layer's channels, by name, for every pixel. Any that
+    // are missing from the layer are 0. None if there's no such layer.
+    #[must_use]
+    pub fn pixels(&self, name: &str, channels: &[&str]) -> Option<Vec<Vec3>> {
+        let layer = self.layers.iter().find(|l| l.name == name)?;
+        let n_pixels = self.width * self.height;
+        let planes: Vec<Option<&[f32]>> = channels.iter().map(|ch| {
+            layer.channels.iter().position(|c| c == ch)
+                .map(|c| &layer.data[c * n_pixels..(c + 1) * n_pixels])
+        }).collect();
+        Some((0..n_pixels).map(|i| {
+            let mut v = Vec3::new();
+            for (c, plane) in planes.iter().enumerate().take(3) {
+                v[c] = plane.map_or(0.0, |p| f64::from(p[i]));
+            }
+            v
+        }).collect())
+    }
+
+    // Replace the named layer with these pixels, or add it if it's new
+    pub fn set_layer(&mut self, name: &str, channels: &[&str], pixels: &[Vec3]) {
+        let layer = match self.layers.iter().position(|l| l.name == name) {
+            Some(i) => {
+                self.layers[i] = FilmLayer {
+                    name: name.to_string(),
+                    channels: channels.iter().map(ToString::to_string).collect(),
+                    data: vec![0.0; channels.len() * self.width * self.height],
+                };
+                i
+            },
+            None => self.add_layer(name, channels),
+        };
+        let mut values = vec![0.0; channels.len()];
+        for (i, p) in pixels.iter().enumerate() {
+            for (c, v) in values.iter_mut().enumerate() {
+                *v = p[c];
+            }
+            self.set(layer, i % self.width, i / self.width, &values);
+        }
+    }
+
+    /// # Errors
+    ///
+    /// If the file can't be read, or it's not an image this could have
+    /// written (layers of different sizes)
+    pub fn read_exr(path: &Path) -> exr::error::Result<Self> {
+        let image = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .all_layers()
+            .all_attributes()
+            .from_file(path)?;
+
+        let size = image.layer_data.first().map_or(Vec2(0, 0), |l| l.size);
+        let mut film = Self::new(size.0, size.1);
+        for layer in &image.layer_data {
+            if layer.size != size {
+                return Err(exr::error::Error::Invalid("layers of different sizes".into()));
+            }
+            film.layers.push(FilmLayer {
+                name: layer.attributes.layer_name.as_ref()
+                    .map_or_else(String::new, ToString::to_string),
+                channels: layer.channel_data.list.iter().map(|c| c.name.to_string()).collect(),
+                data: layer.channel_data.list.iter()
+                    .flat_map(|c| c.sample_data.values_as_f32())
+                    .collect(),
+            });
+        }
+        Ok(film)
+    }
+
+    /// # Errors
+    ///
+    /// If the file can't be written
+    pub fn write_exr(&self, path: &Path) -> exr::error::UnitResult {
+        let size = Vec2(self.width, self.height);
+        let n_pixels = self.width * self.height;
+        let layers: Vec<_> = self.layers.iter().map(|layer| {
+            let channels: SmallVec<[AnyChannel<FlatSamples>; 4]> = layer.channels.iter()
+                .enumerate()
+                .map(|(c, name)| AnyChannel::new(
+                    name.as_str(),
+                    FlatSamples::F32(layer.data[c * n_pixels..(c + 1) * n_pixels].to_vec()),
+                ))
+                .collect();
+            Layer::new(
+                size,
+                LayerAttributes::named(layer.name.as_str()),
+                Encoding::FAST_LOSSLESS,
+                AnyChannels::sort(channels),
+            )
+        }).collect();
+
+        let attributes = ImageAttributes::new(IntegerBounds::from_dimensions(size));
+        Image::from_layers(attributes, layers).write().to_file(path)
+    }
+}
@@ -0,0 +1,390 @@
+use crate::vec3::{Vec3,Color,dot,cross};
+use crate::ray::Ray;
+use crate::hit::HitRecord;
+use crate::material::{Material,ScatterRecord};
+use crate::pdf::PDensityFn;
+use crate::util::{random,PI};
+
+use std::sync::Arc;
+
+// Hair BSDF from Chiang et al., "A Practical and Controllable Hair and Fur
+// Model for Production Path Tracing" (2016), by way of d'Eon et al. and pbrt.
+// Light either reflects off the cuticle (R), goes through the fiber (TT),
+// reflects once inside it (TRT), or bounces around some more, which we lump
+// into a single isotropic term. Meant for use with curve::Curve, which tells
+// us the fiber direction (dpdu) and where across it we hit (the normal).
+pub struct Hair {
+    // absorption inside the fiber, per unit of fiber diameter
+    pub sigma_a: Color,
+    // longitudinal & azimuthal roughness, in [0,1]
+    pub beta_m: f64,
+    pub beta_n: f64,
+    // tilt of the cuticle scales, in degrees
+    pub alpha: f64,
+    pub eta: f64,
+}
+
+// Lobes we model explicitly; everything past TRT goes in the last one
+const P_MAX : usize = 3;
+
+const SQRT_PI_OVER_8 : f64 = 0.626_657_068_657_750_1;
+
+impl Hair {
+    #[must_use]
+    pub fn new(sigma_a: Color, beta_m: f64, beta_n: f64) -> Self {
+        Self {
+            sigma_a,
+            beta_m: beta_m.clamp(0.01, 1.0),
+            beta_n: beta_n.clamp(0.01, 1.0),
+            alpha: 2.0,
+            eta: 1.55,
+        }
+    }
+
+    // Color from the concentration of the two kinds of melanin in hair.
+    // Eumelanin goes from blond (~0.3) to brown (~1.3) to black (~8), and
+    // pheomelanin makes it red.
+    #[must_use]
+    pub fn from_melanin(eumelanin: f64, pheomelanin: f64, beta_m: f64, beta_n: f64) -> Self {
+        let sigma_a = eumelanin * Color(0.419, 0.697, 1.37)
+            + pheomelanin * Color(0.187, 0.4, 1.05);
+        Self::new(sigma_a, beta_m, beta_n)
+    }
+
+    // Find the absorption that gives roughly color c after multiple scattering
+    #[must_use]
+    pub fn from_color(c: Color, beta_m: f64, beta_n: f64) -> Self {
+        let b = beta_n.clamp(0.01, 1.0);
+        let denom = 5.969 - 0.215 * b + 2.532 * b.powi(2) - 10.73 * b.powi(3)
+            + 5.574 * b.powi(4) + 0.245 * b.powi(5);
+        let sigma = |x: f64| (x.max(1e-4).ln() / denom).powi(2);
+        Self::new(Color(sigma(c.r()), sigma(c.g()), sigma(c.b())), beta_m, beta_n)
+    }
+
+    // Everything that depends on the hit but not on the outgoing direction
+    fn lobes(&self, ray_in: &Ray, rec: &HitRecord) -> HairLobes {
+        // local frame: x along the fiber, z facing the incoming ray
+        let x = rec.shading_geo.dpdu.unit_vector();
+        let wo = -ray_in.dir.unit_vector();
+        let z = (wo - dot(wo, x) * x).unit_vector();
+        let y = cross(z, x);
+        let h = dot(rec.norm, y).clamp(-1.0, 1.0);
+
+        let beta_m = self.beta_m;
+        let beta_n = self.beta_n;
+        let v0 = (0.726 * beta_m + 0.812 * beta_m.powi(2) + 3.7 * beta_m.powi(20)).powi(2);
+        let v = [v0, 0.25 * v0, 4.0 * v0, 4.0 * v0];
+        let s = SQRT_PI_OVER_8
+            * (0.265 * beta_n + 1.194 * beta_n.powi(2) + 5.372 * beta_n.powi(22));
+
+        // tilts for the first three lobes are alpha, -2 alpha, -4 alpha
+        let mut sin_2k_alpha = [0.0; 3];
+        let mut cos_2k_alpha = [0.0; 3];
+        sin_2k_alpha[0] = self.alpha.to_radians().sin();
+        cos_2k_alpha[0] = f64::sqrt(1.0 - sin_2k_alpha[0] * sin_2k_alpha[0]);
+        for i in 1..3 {
+            sin_2k_alpha[i] = 2.0 * cos_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+            cos_2k_alpha[i] = cos_2k_alpha[i - 1] * cos_2k_alpha[i - 1]
+                - sin_2k_alpha[i - 1] * sin_2k_alpha[i - 1];
+        }
+
+        let local_wo = Vec3(dot(wo, x), dot(wo, y), dot(wo, z));
+        let sin_theta_o = local_wo.x();
+        let cos_theta_o = f64::sqrt(f64::max(0.0, 1.0 - sin_theta_o * sin_theta_o));
+        let phi_o = f64::atan2(local_wo.z(), local_wo.y());
+
+        // refracted direction inside the fiber
+        let sin_theta_t = sin_theta_o / self.eta;
+        let cos_theta_t = f64::sqrt(f64::max(0.0, 1.0 - sin_theta_t * sin_theta_t));
+        let etap = f64::sqrt(self.eta * self.eta - sin_theta_o * sin_theta_o) / cos_theta_o;
+        let sin_gamma_t = h / etap;
+        let cos_gamma_t = f64::sqrt(f64::max(0.0, 1.0 - sin_gamma_t * sin_gamma_t));
+        let gamma_t = sin_gamma_t.clamp(-1.0, 1.0).asin();
+
+        let tr = (-2.0 * cos_gamma_t / cos_theta_t * self.sigma_a).exp();
+        let ap = attenuations(cos_theta_o, self.eta, h, tr);
+
+        let luminance = |c: Color| 0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b();
+        let sum_y: f64 = ap.iter().map(|a| luminance(*a)).sum();
+        let mut ap_pdf = [0.0; P_MAX + 1];
+        for (p, a) in ap.iter().enumerate() {
+            ap_pdf[p] = if sum_y > 0.0 { luminance(*a) / sum_y } else { 0.0 };
+        }
+
+        HairLobes {
+            x, y, z,
+            v, s,
+            sin_2k_alpha, cos_2k_alpha,
+            sin_theta_o, cos_theta_o, phi_o,
+            gamma_o: h.asin(), gamma_t,
+            ap, ap_pdf,
+        }
+    }
+}
+
+impl From<Hair> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Hair) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Hair {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: None,
+            specular_color: None,
+            spectral: None,
+            // the color comes from absorption, in scattering_value
+            attenuation: Color(1.0, 1.0, 1.0),
+            pdf: HairPDF { lobes: self.lobes(ray_in, rec) }.into(),
+        })
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let v = self.scattering_value(ray_in, rec, Color(1.0, 1.0, 1.0), scattered);
+        (v.r() + v.g() + v.b()) / 3.0
+    }
+
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        attenuation * self.lobes(ray_in, rec).eval(scattered.dir.unit_vector())
+    }
+}
+
+// Attenuation for each lobe: Fresnel at the cuticle, and absorption (tr) for
+// each trip through the fiber. The last one sums the geometric series for
+// all the longer paths.
+fn attenuations(cos_theta_o: f64, eta: f64, h: f64, tr: Color) -> [Color; P_MAX + 1] {
+    let cos_gamma_o = f64::sqrt(f64::max(0.0, 1.0 - h * h));
+    let f = fresnel_dielectric(cos_theta_o * cos_gamma_o, 1.0, eta);
+
+    let r = Color(f, f, f);
+    let tt = (1.0 - f) * (1.0 - f) * tr;
+    let trt = tt * tr * f;
+    let rest = trt * f * tr;
+    let rest = Color(
+        rest.r() / (1.0 - tr.r() * f),
+        rest.g() / (1.0 - tr.g() * f),
+        rest.b() / (1.0 - tr.b() * f),
+    );
+    [r, tt, trt, rest]
+}
+
+fn fresnel_dielectric(cos_i: f64, eta_i: f64, eta_t: f64) -> f64 {
+    let (cos_i, eta_i, eta_t) = if cos_i < 0.0 {
+        (-cos_i, eta_t, eta_i)
+    } else {
+        (cos_i, eta_i, eta_t)
+    };
+    let cos_i = cos_i.min(1.0);
+    let sin_i = f64::sqrt(f64::max(0.0, 1.0 - cos_i * cos_i));
+    let sin_t = eta_i / eta_t * sin_i;
+    if sin_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f64::sqrt(f64::max(0.0, 1.0 - sin_t * sin_t));
+    let r_parl = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+    let r_perp = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    0.5 * (r_parl * r_parl + r_perp * r_perp)
+}
+
+fn bessel_i0(x: f64) -> f64 {
+    let mut val = 0.0;
+    let mut x2i = 1.0;
+    let mut ifact = 1.0;
+    let mut i4 = 1.0;
+    for i in 0..10 {
+        if i > 1 {
+            ifact *= f64::from(i);
+        }
+        val += x2i / (i4 * ifact * ifact);
+        x2i *= x * x;
+        i4 *= 4.0;
+    }
+    val
+}
+
+fn log_bessel_i0(x: f64) -> f64 {
+    if x > 12.0 {
+        x + 0.5 * (-f64::ln(2.0 * PI) + f64::ln(1.0 / x) + 1.0 / (8.0 * x))
+    } else {
+        bessel_i0(x).ln()
+    }
+}
+
+// Longitudinal scattering
+fn mp(cos_theta_i: f64, cos_theta_o: f64, sin_theta_i: f64, sin_theta_o: f64, v: f64) -> f64 {
+    let a = cos_theta_i * cos_theta_o / v;
+    let b = sin_theta_i * sin_theta_o / v;
+    if v <= 0.1 {
+        f64::exp(log_bessel_i0(a) - b - 1.0 / v + std::f64::consts::LN_2 + f64::ln(1.0 / (2.0 * v)))
+    } else {
+        f64::exp(-b) * bessel_i0(a) / (f64::sinh(1.0 / v) * 2.0 * v)
+    }
+}
+
+fn logistic(x: f64, s: f64) -> f64 {
+    let x = x.abs();
+    f64::exp(-x / s) / (s * (1.0 + f64::exp(-x / s)).powi(2))
+}
+
+fn logistic_cdf(x: f64, s: f64) -> f64 {
+    1.0 / (1.0 + f64::exp(-x / s))
+}
+
+fn trimmed_logistic(x: f64, s: f64, a: f64, b: f64) -> f64 {
+    logistic(x, s) / (logistic_cdf(b, s) - logistic_cdf(a, s))
+}
+
+fn sample_trimmed_logistic(u: f64, s: f64, a: f64, b: f64) -> f64 {
+    let k = logistic_cdf(b, s) - logistic_cdf(a, s);
+    let x = -s * f64::ln(1.0 / (u * k + logistic_cdf(a, s)) - 1.0);
+    x.clamp(a, b)
+}
+
+// Azimuthal direction of lobe p
+fn phi(p: usize, gamma_o: f64, gamma_t: f64) -> f64 {
+    let p = p as f64;
+    2.0 * p * gamma_t - 2.0 * gamma_o + p * PI
+}
+
+// Azimuthal scattering
+fn np(dphi: f64, p: usize, s: f64, gamma_o: f64, gamma_t: f64) -> f64 {
+    let mut d = dphi - phi(p, gamma_o, gamma_t);
+    while d > PI {
+        d -= 2.0 * PI;
+    }
+    while d < -PI {
+        d += 2.0 * PI;
+    }
+    trimmed_logistic(d, s, -PI, PI)
+}
+
+struct HairLobes {
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    v: [f64; P_MAX + 1],
+    s: f64,
+    sin_2k_alpha: [f64; 3],
+    cos_2k_alpha: [f64; 3],
+    sin_theta_o: f64,
+    cos_theta_o: f64,
+    phi_o: f64,
+    gamma_o: f64,
+    gamma_t: f64,
+    ap: [Color; P_MAX + 1],
+    ap_pdf: [f64; P_MAX + 1],
+}
+
+impl HairLobes {
+    // theta_o, tilted by the cuticle scales for lobe p
+    fn tilted(&self, p: usize) -> (f64, f64) {
+        let (so, co) = (self.sin_theta_o, self.cos_theta_o);
+        let (sin_op, cos_op) = match p {
+            0 => (so * self.cos_2k_alpha[1] - co * self.sin_2k_alpha[1],
+                  co * self.cos_2k_alpha[1] + so * self.sin_2k_alpha[1]),
+            1 => (so * self.cos_2k_alpha[0] + co * self.sin_2k_alpha[0],
+                  co * self.cos_2k_alpha[0] - so * self.sin_2k_alpha[0]),
+            2 => (so * self.cos_2k_alpha[2] + co * self.sin_2k_alpha[2],
+                  co * self.cos_2k_alpha[2] - so * self.sin_2k_alpha[2]),
+            _ => (so, co),
+        };
+        (sin_op, cos_op.abs())
+    }
+
+    fn angles(&self, wi: Vec3) -> (f64, f64, f64) {
+        let sin_theta_i = dot(wi, self.x);
+        let cos_theta_i = f64::sqrt(f64::max(0.0, 1.0 - sin_theta_i * sin_theta_i));
+        let phi_i = f64::atan2(dot(wi, self.z), dot(wi, self.y));
+        (sin_theta_i, cos_theta_i, phi_i)
+    }
+
+    // Sum of lobe terms, each weighted by w(p)
+    fn sum<F: Fn(usize) -> f64>(&self, wi: Vec3, w: F) -> Vec<f64> {
+        let (sin_theta_i, cos_theta_i, phi_i) = self.angles(wi);
+        let dphi = phi_i - self.phi_o;
+        (0..=P_MAX).map(|p| {
+            if p < P_MAX {
+                let (sin_op, cos_op) = self.tilted(p);
+                mp(cos_theta_i, cos_op, sin_theta_i, sin_op, self.v[p])
+                    * np(dphi, p, self.s, self.gamma_o, self.gamma_t)
+                    * w(p)
+            } else {
+                mp(cos_theta_i, self.cos_theta_o, sin_theta_i, self.sin_theta_o, self.v[p])
+                    * w(p) / (2.0 * PI)
+            }
+        }).collect()
+    }
+
+    // BSDF times cosine for direction wi
+    fn eval(&self, wi: Vec3) -> Color {
+        let terms = self.sum(wi, |_| 1.0);
+        let mut f = Color(0.0, 0.0, 0.0);
+        for (p, t) in terms.iter().enumerate() {
+            f += *t * self.ap[p];
+        }
+        f
+    }
+
+    fn pdf(&self, wi: Vec3) -> f64 {
+        self.sum(wi, |p| self.ap_pdf[p]).iter().sum()
+    }
+
+    fn sample(&self) -> Vec3 {
+        // pick a lobe by how much energy it carries
+        let mut u = random::double();
+        let mut p = P_MAX;
+        for (i, pdf) in self.ap_pdf.iter().enumerate() {
+            if u < *pdf {
+                p = i;
+                break;
+            }
+            u -= pdf;
+        }
+
+        let (sin_op, cos_op) = self.tilted(p);
+
+        // longitudinal
+        let v = self.v[p];
+        let u1 = f64::max(random::double(), 1e-5);
+        let cos_theta = 1.0 + v * f64::ln(u1 + (1.0 - u1) * f64::exp(-2.0 / v));
+        let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta * cos_theta));
+        let cos_phi = f64::cos(2.0 * PI * random::double());
+        let sin_theta_i = -cos_theta * sin_op + sin_theta * cos_phi * cos_op;
+        let cos_theta_i = f64::sqrt(f64::max(0.0, 1.0 - sin_theta_i * sin_theta_i));
+
+        // azimuthal
+        let dphi = if p < P_MAX {
+            phi(p, self.gamma_o, self.gamma_t)
+                + sample_trimmed_logistic(random::double(), self.s, -PI, PI)
+        } else {
+            2.0 * PI * random::double()
+        };
+        let phi_i = self.phi_o + dphi;
+
+        sin_theta_i * self.x
+            + cos_theta_i * f64::cos(phi_i) * self.y
+            + cos_theta_i * f64::sin(phi_i) * self.z
+    }
+}
+
+struct HairPDF {
+    lobes: HairLobes,
+}
+
+impl From<HairPDF> for Arc<dyn PDensityFn + Sync + Send> {
+    fn from(pdf: HairPDF) -> Arc<dyn PDensityFn + Sync + Send> {
+        Arc::new(pdf)
+    }
+}
+
+impl PDensityFn for HairPDF {
+    fn value(&self, dir: Vec3) -> f64 {
+        self.lobes.pdf(dir.unit_vector())
+    }
+
+    fn generate(&self, _sr: &mut ScatterRecord) -> Vec3 {
+        self.lobes.sample()
+    }
+}
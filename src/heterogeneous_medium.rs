@@ -0,0 +1,251 @@
+use crate::vec3::{Vec3,Point3,Color};
+use crate::ray::Ray;
+use crate::hit::{HitRecord,Hittable};
+use crate::material::{Material,Isotropic};
+use crate::aabb::AABB;
+use crate::perlin::Perlin;
+use crate::util::random;
+
+use std::sync::Arc;
+
+// A spatially varying density. Points are given in the local coordinates of
+// the medium's bounding box, i.e. [0,1] along each axis.
+pub trait Density {
+    fn density(&self, p: Point3) -> f64;
+
+    // Upper bound on `density` anywhere in the box. Used as the majorant for
+    // delta/ratio tracking, so it must not underestimate.
+    fn max_density(&self) -> f64;
+}
+
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f64>,
+    max: f64,
+}
+
+impl DensityGrid {
+    /// # Panics
+    ///
+    /// Panics if `data` doesn't hold exactly nx * ny * nz voxels
+    #[must_use]
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f64>) -> Self {
+        assert!(nx > 0 && ny > 0 && nz > 0, "empty density grid");
+        assert!(data.len() == nx * ny * nz, "data.len(): {}", data.len());
+        let max = data.iter().fold(0.0, |acc: f64, d| acc.max(*d));
+        Self {
+            nx, ny, nz, data, max,
+        }
+    }
+
+    // Fill the grid by evaluating f at each voxel center (local coordinates)
+    #[must_use]
+    pub fn from_fn<F>(nx: usize, ny: usize, nz: usize, f: F) -> Self
+    where F: Fn(Point3) -> f64 {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    data.push(f(Point3(
+                        (i as f64 + 0.5) / nx as f64,
+                        (j as f64 + 0.5) / ny as f64,
+                        (k as f64 + 0.5) / nz as f64,
+                    )));
+                }
+            }
+        }
+        Self::new(nx, ny, nz, data)
+    }
+
+    fn voxel(&self, i: i64, j: i64, k: i64) -> f64 {
+        if i < 0 || j < 0 || k < 0 {
+            return 0.0;
+        }
+        let (i, j, k) = (i as usize, j as usize, k as usize);
+        if i >= self.nx || j >= self.ny || k >= self.nz {
+            return 0.0;
+        }
+        self.data[(k * self.ny + j) * self.nx + i]
+    }
+}
+
+impl From<DensityGrid> for Arc<dyn Density + Sync + Send> {
+    fn from(dd: DensityGrid) -> Arc<dyn Density + Sync + Send> {
+        Arc::new(dd)
+    }
+}
+
+impl Density for DensityGrid {
+    // Trilinear interpolation between voxel centers
+    fn density(&self, p: Point3) -> f64 {
+        let x = p.x() * self.nx as f64 - 0.5;
+        let y = p.y() * self.ny as f64 - 0.5;
+        let z = p.z() * self.nz as f64 - 0.5;
+
+        let (i, j, k) = (x.floor(), y.floor(), z.floor());
+        let (u, v, w) = (x - i, y - j, z - k);
+        let (i, j, k) = (i as i64, j as i64, k as i64);
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let weight =
+                        (if di == 1 { u } else { 1.0 - u }) *
+                        (if dj == 1 { v } else { 1.0 - v }) *
+                        (if dk == 1 { w } else { 1.0 - w });
+                    accum += weight * self.voxel(i + di, j + dj, k + dk);
+                }
+            }
+        }
+        accum
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max
+    }
+}
+
+// Procedural density from Perlin turbulence, e.g. for clouds. `scale` is the
+// noise frequency across the box, and the result is clamped to [0, density].
+pub struct NoiseDensity {
+    noise: Perlin,
+    scale: f64,
+    density: f64,
+}
+
+impl NoiseDensity {
+    #[must_use]
+    pub fn new(density: f64, scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale, density,
+        }
+    }
+}
+
+impl From<NoiseDensity> for Arc<dyn Density + Sync + Send> {
+    fn from(dd: NoiseDensity) -> Arc<dyn Density + Sync + Send> {
+        Arc::new(dd)
+    }
+}
+
+impl Density for NoiseDensity {
+    fn density(&self, p: Point3) -> f64 {
+        self.density * self.noise.turb(self.scale * p, None).clamp(0.0, 1.0)
+    }
+
+    fn max_density(&self) -> f64 {
+        self.density
+    }
+}
+
+pub struct HeterogeneousMedium {
+    bounds: AABB,
+    density: Arc<dyn Density + Sync + Send>,
+    phase_fn: Arc<dyn Material + Sync + Send>,
+}
+
+impl HeterogeneousMedium {
+    #[must_use]
+    pub fn new(bounds: AABB, density: Arc<dyn Density + Sync + Send>, c: Color)
+               -> Self {
+        Self::with_phase(bounds, density, Isotropic::new(c).into())
+    }
+
+    #[must_use]
+    pub fn with_phase(bounds: AABB, density: Arc<dyn Density + Sync + Send>,
+                      phase_fn: Arc<dyn Material + Sync + Send>) -> Self {
+        Self {
+            bounds, density, phase_fn,
+        }
+    }
+
+    fn density_at(&self, p: Point3) -> f64 {
+        let extent = self.bounds.max - self.bounds.min;
+        let local = p - self.bounds.min;
+        self.density.density(Point3(
+            local.x() / extent.x(),
+            local.y() / extent.y(),
+            local.z() / extent.z(),
+        ))
+    }
+
+    // Free-flight distance (in ray parameter units) through a homogeneous
+    // medium of the majorant density.
+    fn step(sigma_max: f64, ray_length: f64) -> f64 {
+        -f64::ln(1.0 - random::double()) / (sigma_max * ray_length)
+    }
+}
+
+impl From<HeterogeneousMedium> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: HeterogeneousMedium) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    // Delta (Woodcock) tracking: take exponential steps against the majorant
+    // and accept a real collision with probability density / majorant. The
+    // rest are null collisions, so we keep walking until we leave the box.
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let sigma_max = self.density.max_density();
+        if sigma_max <= 0.0 {
+            return None;
+        }
+
+        let (t0, t1) = self.bounds.hit_range(r, t_min, t_max)?;
+        let ray_length = r.dir.len();
+
+        let mut t = t0;
+        loop {
+            t += Self::step(sigma_max, ray_length);
+            if t >= t1 {
+                return None;
+            }
+            let p = r.at(t);
+            if random::double() * sigma_max < self.density_at(p) {
+                return Some(HitRecord::new(
+                    r, p, Vec3(1.0, 0.0, 0.0), t, 0.0, 0.0, self.phase_fn.clone()
+                ));
+            }
+        }
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.phase_fn);
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        Some(self.bounds)
+    }
+
+    // Ratio tracking: same steps as delta tracking, but instead of stopping
+    // at the first real collision we weight by the probability of a null one.
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let sigma_max = self.density.max_density();
+        if sigma_max <= 0.0 {
+            return 1.0;
+        }
+
+        let Some((t0, t1)) = self.bounds.hit_range(r, t_min, t_max) else {
+            return 1.0;
+        };
+        let ray_length = r.dir.len();
+
+        let mut tr = 1.0;
+        let mut t = t0;
+        loop {
+            t += Self::step(sigma_max, ray_length);
+            if t >= t1 {
+                return tr;
+            }
+            tr *= 1.0 - self.density_at(r.at(t)) / sigma_max;
+            if tr <= 0.0 {
+                return 0.0;
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::vec3::{Vec3, Point3, dot, Axis, cross};
 use crate::ray::Ray;
 use crate::material::Material;
+use crate::medium::MediumInterface;
 use crate::aabb::AABB;
 use crate::util;
 
@@ -27,6 +28,15 @@ pub struct HitRecord {
     pub v: f64,
     pub front_face: bool,
     pub shading_geo: ShadingGeometry,
+    // media on either side of the surface, if it declares any
+    pub interface: Option<Arc<MediumInterface>>,
+    // IOR on the far side of the surface from its interior. Filled in by the
+    // integrator from the medium stack; 1.0 (air) otherwise.
+    pub eta_ext: f64,
+    // Which object of the scene this is on, counting from 1 in the order
+    // they were added to it (0 if it isn't known). Filled in by the lists
+    // and BVHs objects are added to, the outermost one winning.
+    pub object_id: u32,
 }
 
 impl HitRecord {
@@ -57,7 +67,10 @@ impl HitRecord {
                 n: norm,
                 dpdu: Vec3::new(),
                 dpdv: Vec3::new(),
-            }
+            },
+            interface: None,
+            eta_ext: 1.0,
+            object_id: 0,
         }
     }
 
@@ -116,6 +129,39 @@ pub trait Hittable {
     fn empty(&self) -> bool {
         false
     }
+
+    // A uniformly random point on the surface and the outward normal there,
+    // for starting paths of light from it. Only shapes that get used as
+    // lights need to give one.
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        None
+    }
+
+    // Surface area, to go with sample_surface
+    fn area(&self) -> f64 {
+        0.0
+    }
+
+    // Call f with each material this is made of, in the order they were
+    // given to it, so they can be numbered the same way on every run
+    fn materials(&self, _f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {}
+
+    // Fraction of light that makes it along the ray between t_min and t_max.
+    // Solid surfaces simply block the segment, unless their material lets the
+    // light through where it's hit (see Material::transparent); participating
+    // media override this with an actual transmittance estimate, and
+    // aggregates combine their children, so a shadow ray can be traced
+    // against the whole world.
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut t_min = t_min;
+        while let Some(hr) = self.hit(r, t_min, t_max) {
+            if !hr.mat.transparent(r, &hr) {
+                return 0.0;
+            }
+            t_min = hr.t + 0.001;
+        }
+        1.0
+    }
 }
 
 pub struct Translate {
@@ -143,13 +189,16 @@ impl Hittable for Translate {
             origin: r.origin - self.offset,
             dir: r.dir,
             time: r.time,
+            wavelengths: r.wavelengths,
         };
         if let Some(hr) = self.obj.hit(&moved_r, t_min, t_max) {
-            Some(HitRecord::with_dps(
+            let mut moved_hr = HitRecord::with_dps(
                 &moved_r, hr.p + self.offset, hr.norm,
                 hr.t, hr.u, hr.v, hr.mat.clone(),
                 hr.shading_geo.dpdu, hr.shading_geo.dpdv,
-            ))
+            );
+            moved_hr.interface = hr.interface;
+            Some(moved_hr)
         } else {
             None
         }
@@ -169,6 +218,28 @@ impl Hittable for Translate {
     fn random(&self, origin: Vec3) -> Vec3 {
         self.obj.random(origin)
     }
+
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        self.obj.sample_surface().map(|(p, n)| (p + self.offset, n))
+    }
+
+    fn area(&self) -> f64 {
+        self.obj.area()
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.obj.materials(f);
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let moved_r = Ray {
+            origin: r.origin - self.offset,
+            dir: r.dir,
+            time: r.time,
+            wavelengths: r.wavelengths,
+        };
+        self.obj.transmittance(&moved_r, t_min, t_max)
+    }
 }
 
 pub struct Rotate {
@@ -262,30 +333,14 @@ impl Rotate {
         Self::rot_coeffs(v.0, v.1, v.2, axis)
     }
 
-    fn off_axes(rot_axis: Axis) -> (usize, usize) {
-        match rot_axis {
-            Axis::X => (1, 2),
-            Axis::Y => (0, 2),
-            Axis::Z => (0, 1),
-        }
-    }
-}
-
-impl From<Rotate> for Arc<dyn Hittable + Sync + Send> {
-    fn from(hh: Rotate) -> Arc<dyn Hittable + Sync + Send> {
-        Arc::new(hh)
-    }
-}
-
-impl Hittable for Rotate {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // Basically rotate the input Ray opposite the specified rotation
+    fn rotate_ray(&self, r: &Ray) -> Ray {
         let mut origin = r.origin;
         let mut dir = r.dir;
 
         let sin_neg_theta = -self.sin_theta;
         let cos_neg_theta = self.cos_theta;
 
-        // Basically rotate the input Ray opposite the specified rotation
         let (a_axis, b_axis) = Self::off_axes(self.axis);
         let (a_coeff, b_coeff) = Self::rot_coeffs_vec(r.origin, self.axis);
         origin[a_axis] =
@@ -299,7 +354,38 @@ impl Hittable for Rotate {
         dir[b_axis] =
             sin_neg_theta * b_coeff.0 + cos_neg_theta * b_coeff.1;
 
-        let rotated_r = Ray { origin, dir, time: r.time };
+        Ray { origin, dir, time: r.time, wavelengths: r.wavelengths }
+    }
+
+    // Rotate a point or vector the way the object is rotated
+    fn rotate_vec(&self, v: Vec3) -> Vec3 {
+        let mut out = v;
+        let (a_axis, b_axis) = Self::off_axes(self.axis);
+        let (a_coeff, b_coeff) = Self::rot_coeffs_vec(v, self.axis);
+        out[a_axis] = self.cos_theta * a_coeff.0 + self.sin_theta * a_coeff.1;
+        out[b_axis] = self.sin_theta * b_coeff.0 + self.cos_theta * b_coeff.1;
+        out
+    }
+
+    fn off_axes(rot_axis: Axis) -> (usize, usize) {
+        match rot_axis {
+            Axis::X => (1, 2),
+            Axis::Y => (0, 2),
+            Axis::Z => (0, 1),
+        }
+    }
+}
+
+impl From<Rotate> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: Rotate) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for Rotate {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let rotated_r = self.rotate_ray(r);
+        let (a_axis, b_axis) = Self::off_axes(self.axis);
 
         let hr = self.obj.hit(&rotated_r, t_min, t_max)?;
 
@@ -319,10 +405,12 @@ impl Hittable for Rotate {
         normal[b_axis] =
             self.sin_theta * b_coeff.0 + self.cos_theta * b_coeff.1;
 
-        Some(HitRecord::with_dps(
+        let mut rotated_hr = HitRecord::with_dps(
             &rotated_r, p, normal, hr.t, hr.u, hr.v, hr.mat.clone(),
             hr.shading_geo.dpdu, hr.shading_geo.dpdv,
-        ))
+        );
+        rotated_hr.interface = hr.interface;
+        Some(rotated_hr)
 
     }
 
@@ -337,6 +425,23 @@ impl Hittable for Rotate {
     fn random(&self, origin: Vec3) -> Vec3 {
         self.obj.random(origin)
     }
+
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        let (p, n) = self.obj.sample_surface()?;
+        Some((self.rotate_vec(p), self.rotate_vec(n)))
+    }
+
+    fn area(&self) -> f64 {
+        self.obj.area()
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.obj.materials(f);
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.obj.transmittance(&self.rotate_ray(r), t_min, t_max)
+    }
 }
 
 pub struct FlipFace {
@@ -381,4 +486,20 @@ impl Hittable for FlipFace {
     fn random(&self, origin: Vec3) -> Vec3 {
         self.obj.random(origin)
     }
+
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        self.obj.sample_surface().map(|(p, n)| (p, -n))
+    }
+
+    fn area(&self) -> f64 {
+        self.obj.area()
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.obj.materials(f);
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.obj.transmittance(r, t_min, t_max)
+    }
 }
@@ -1,6 +1,7 @@
 use crate::ray::Ray;
 use crate::hit::{HitRecord,Hittable};
 use crate::aabb::AABB;
+use crate::material::Material;
 use crate::vec3::{Vec3, Point3};
 use crate::util::random;
 
@@ -49,9 +50,10 @@ impl Hittable for HittableList {
         let mut opt_rec : Option<HitRecord> = None;
         let mut closest : f64 = t_max;
 
-        for obj in &self.objects {
-            if let Some(hr) = obj.hit(r, t_min, closest) {
+        for (i, obj) in self.objects.iter().enumerate() {
+            if let Some(mut hr) = obj.hit(r, t_min, closest) {
                 closest = hr.t;
+                hr.object_id = i as u32 + 1;
                 opt_rec = Some(hr);
             }
         }
@@ -97,5 +99,43 @@ impl Hittable for HittableList {
     fn empty(&self) -> bool {
         self.objects.is_empty()
     }
+
+    // Picks an object by its share of the area
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        let total = self.area();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut target = random::double() * total;
+        for obj in &self.objects {
+            let area = obj.area();
+            if target < area {
+                return obj.sample_surface();
+            }
+            target -= area;
+        }
+        None
+    }
+
+    fn area(&self) -> f64 {
+        self.objects.iter().map(|obj| obj.area()).sum()
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        for obj in &self.objects {
+            obj.materials(f);
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut tr = 1.0;
+        for obj in &self.objects {
+            tr *= obj.transmittance(r, t_min, t_max);
+            if tr <= 0.0 {
+                return 0.0;
+            }
+        }
+        tr
+    }
 }
 
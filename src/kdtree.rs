@@ -0,0 +1,83 @@
+use crate::vec3::Point3;
+
+use std::cmp::Ordering;
+
+// Points with something attached, for finding all of them near a point.
+// Built once and balanced: each node splits its points at the median along
+// the axis they're most spread out on. The tree is implicit in the order of
+// the points; a node is the middle of its range, with its children either
+// side of it.
+pub struct KdTree<T> {
+    points: Vec<(Point3, T)>,
+    // split axis of the node at the same index
+    axes: Vec<usize>,
+}
+
+impl<T> KdTree<T> {
+    #[must_use]
+    pub fn new(mut points: Vec<(Point3, T)>) -> Self {
+        let mut axes = vec![0; points.len()];
+        Self::build(&mut points, &mut axes);
+        Self { points, axes }
+    }
+
+    fn build(points: &mut [(Point3, T)], axes: &mut [usize]) {
+        if points.is_empty() {
+            return;
+        }
+        let mut min = points[0].0;
+        let mut max = points[0].0;
+        for (p, _) in points.iter() {
+            for c in 0..3 {
+                min[c] = min[c].min(p[c]);
+                max[c] = max[c].max(p[c]);
+            }
+        }
+        let extent = max - min;
+        let axis = (0..3).fold(0, |best, c| if extent[c] > extent[best] { c } else { best });
+
+        let mid = points.len() / 2;
+        points.select_nth_unstable_by(mid, |a, b| {
+            a.0[axis].partial_cmp(&b.0[axis]).unwrap_or(Ordering::Equal)
+        });
+        axes[mid] = axis;
+        let (left, right) = points.split_at_mut(mid);
+        let (left_axes, right_axes) = axes.split_at_mut(mid);
+        Self::build(left, left_axes);
+        Self::build(&mut right[1..], &mut right_axes[1..]);
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    // Call f on each point within radius of p
+    pub fn within(&self, p: Point3, radius: f64, mut f: impl FnMut(Point3, &T)) {
+        self.visit(0, self.points.len(), p, radius, &mut f);
+    }
+
+    fn visit(&self, lo: usize, hi: usize, p: Point3, radius: f64,
+             f: &mut impl FnMut(Point3, &T)) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let (q, data) = &self.points[mid];
+        if (*q - p).len_squared() <= radius * radius {
+            f(*q, data);
+        }
+        let axis = self.axes[mid];
+        if p[axis] - radius <= q[axis] {
+            self.visit(lo, mid, p, radius, f);
+        }
+        if p[axis] + radius >= q[axis] {
+            self.visit(mid + 1, hi, p, radius, f);
+        }
+    }
+}
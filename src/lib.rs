@@ -14,6 +14,8 @@ pub mod perlin;
 pub mod aarect;
 pub mod boxx;
 pub mod constant_medium;
+pub mod heterogeneous_medium;
+pub mod medium;
 pub mod onb;
 pub mod pdf;
 pub mod scene;
@@ -22,6 +24,17 @@ pub mod cylinder;
 pub mod disk;
 pub mod triangle_mesh;
 pub mod obj;
+pub mod spectrum;
+pub mod thin_film;
+pub mod merl;
+pub mod curve;
+pub mod hair;
+pub mod transform;
+pub mod animation;
+pub mod exposure;
+pub mod film;
+pub mod denoise;
+pub mod kdtree;
 
 pub use vec3::{
     Vec3,
@@ -1,53 +1,389 @@
 #![allow(unused)]
 
 use hawxide::{
-    Camera, Color, INFINITY, PDensityFn, PI, Ray, Scene, Vec3,
+    Camera, Color, INFINITY, PDensityFn, PI, Point3, Ray, Scene, Vec3,
     pdf, random, scene, write_color,
 };
+use hawxide::animation::AnimatedScene;
+use hawxide::camera::{
+    Aperture, ApertureImage, CameraModel, CameraView, Convergence, Lens, Projection,
+    StereoLayout, StereoRig,
+};
+use hawxide::exposure::{Exposure, Shutter, ShutterCurve, ShutterProfile};
+use hawxide::denoise::Denoiser;
+use hawxide::film::Film;
+use hawxide::hit::{HitRecord, Hittable};
+use hawxide::medium::MediumStack;
+use hawxide::spectrum::Wavelengths;
+use hawxide::vec3::dot;
 use pdf::{HittablePDF, MixturePDF};
 
 use std::fs::File;
 use std::io::{Write, BufWriter};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use rayon::prelude::*;
 use clap::Parser;
 
-fn ray_color(r: &Ray,
+mod bdpt;
+mod sppm;
+
+// If the scattered ray passed through a surface that bounds a volume, note
+// that we're now inside (or outside) of it.
+// Whether hr is really there for a path inside what's on stack, filling in
+// its interface and the IOR outside it. A surface inside something that takes
+// precedence over it doesn't exist, so the path passes through, crossing it
+// on the stack as it goes.
+fn true_hit(stack: &mut MediumStack, r: &Ray, hr: &mut HitRecord) -> bool {
+    if hr.interface.is_none() {
+        hr.interface = hr.mat.interface(r, hr);
+    }
+    if let Some(iface) = &hr.interface {
+        if !stack.is_true_hit(iface, hr.front_face) {
+            stack.cross(iface, hr.front_face);
+            return false;
+        }
+        hr.eta_ext = stack.outside_ior(iface);
+    }
+    true
+}
+
+fn update_media(stack: &mut MediumStack, hr: &HitRecord, dir: Vec3) {
+    if let Some(iface) = &hr.interface {
+        if dot(dir, hr.norm) < 0.0 {
+            stack.cross(iface, hr.front_face);
+        }
+    }
+}
+
+// Light arriving along a ray, split up by where it came from for the output
+// passes. The parts add up to the ray's color.
+#[derive(Clone)]
+struct Radiance {
+    // from the first thing the ray hits (or the background)
+    emitted: Color,
+    // from lights seen from that first surface, one bounce away
+    direct: Color,
+    // everything else
+    indirect: Color,
+    // all of it again, by which of scene.lights it came from. Empty unless
+    // the light passes are wanted.
+    lights: Vec<Color>,
+}
+
+impl Radiance {
+    fn zero(n_lights: usize) -> Self {
+        let black = Color(0.0, 0.0, 0.0);
+        Self { emitted: black, direct: black, indirect: black, lights: vec![black; n_lights] }
+    }
+
+    // Light c given off where the path is now, from scene.lights[light]
+    fn add_emission(&mut self, path: &PathState, c: Color, light: Option<usize>) {
+        self.add_light(path.depth as usize, path.throughput * c, light);
+    }
+
+    // Light c that took depth bounces to get to the camera
+    fn add_light(&mut self, depth: usize, c: Color, light: Option<usize>) {
+        match depth {
+            0 => self.emitted += c,
+            1 => self.direct += c,
+            _ => self.indirect += c,
+        }
+        if let Some(l) = light {
+            self.lights[l] += c;
+        }
+    }
+
+    fn total(&self) -> Color {
+        self.emitted + self.direct + self.indirect
+    }
+
+    fn map(mut self, f: impl Fn(Color) -> Color) -> Self {
+        self.emitted = f(self.emitted);
+        self.direct = f(self.direct);
+        self.indirect = f(self.indirect);
+        for l in &mut self.lights {
+            *l = f(*l);
+        }
+        self
+    }
+
+    fn scale(self, c: Color) -> Self {
+        self.map(|x| c * x)
+    }
+
+    // Scale the indirect light down so no channel is over max. The light
+    // passes shrink to match.
+    fn clamp_indirect(mut self, max: f64) -> Self {
+        let i = self.indirect;
+        let biggest = i.r().max(i.g()).max(i.b());
+        if biggest <= max {
+            return self;
+        }
+        let before = self.total();
+        self.indirect = i * (max / biggest);
+        let after = self.total();
+        let ratio = |a: f64, b: f64| if b > 0.0 { a / b } else { 1.0 };
+        let scale = Color(
+            ratio(after.r(), before.r()),
+            ratio(after.g(), before.g()),
+            ratio(after.b(), before.b()),
+        );
+        for l in &mut self.lights {
+            *l = scale * *l;
+        }
+        self
+    }
+
+    fn add(mut self, other: &Self) -> Self {
+        self.emitted += other.emitted;
+        self.direct += other.direct;
+        self.indirect += other.indirect;
+        for (l, o) in self.lights.iter_mut().zip(&other.lights) {
+            *l += *o;
+        }
+        self
+    }
+}
+
+// Which of scene.lights the ray hit at hr, if any
+fn light_index(scene: &Scene, r: &Ray, hr: &HitRecord) -> Option<usize> {
+    let eps = 1e-6 * hr.t.max(1.0);
+    scene.lights.objects.iter().position(|light| {
+        light.hit(r, 0.001, hr.t + eps).is_some_and(|h| (h.t - hr.t).abs() < eps)
+    })
+}
+
+// Kinds of bounce, which get their own depth limits
+#[derive(Copy, Clone, Debug)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+impl Lobe {
+    // A bounce at hr into dir. Scattering in a medium counts as diffuse: the
+    // normal there is arbitrary, and the phase function is a smooth lobe.
+    fn of(r: &Ray, hr: &HitRecord, dir: Vec3, specular: bool) -> Self {
+        if hr.mat.volumetric(r, hr) {
+            Lobe::Diffuse
+        } else if dot(dir, hr.norm) < 0.0 {
+            Lobe::Transmission
+        } else if specular {
+            Lobe::Specular
+        } else {
+            Lobe::Diffuse
+        }
+    }
+}
+
+// How many bounces of a kind a path takes before Russian roulette may end
+// it, and at most
+#[derive(Copy, Clone, Debug)]
+struct DepthLimits {
+    min: u32,
+    max: u32,
+}
+
+#[derive(Copy, Clone)]
+struct PathLimits {
+    // bounces of any kind
+    max_depth: u32,
+    diffuse: DepthLimits,
+    specular: DepthLimits,
+    transmission: DepthLimits,
+    // most a channel of the indirect light can add to a sample
+    clamp: Option<f64>,
+}
+
+impl PathLimits {
+    fn of(&self, lobe: Lobe) -> DepthLimits {
+        match lobe {
+            Lobe::Diffuse => self.diffuse,
+            Lobe::Specular => self.specular,
+            Lobe::Transmission => self.transmission,
+        }
+    }
+}
+
+// Everything the paths of a render share
+struct Tracer {
+    limits: PathLimits,
+    // for the light passes; 0 if they're not wanted
+    n_lights: usize,
+    // samples thrown away for being NaN or infinite
+    bad_samples: AtomicUsize,
+    // paths cut short because a pdf came out zero (or worse)
+    bad_pdfs: AtomicUsize,
+}
+
+// How far along a path is
+#[derive(Copy, Clone)]
+struct PathState {
+    depth: u32,
+    // by Lobe
+    bounces: [u32; 3],
+    // how much of the light from further along makes it back to the camera
+    throughput: Color,
+}
+
+impl PathState {
+    fn new() -> Self {
+        Self { depth: 0, bounces: [0; 3], throughput: Color(1.0, 1.0, 1.0) }
+    }
+
+    // The path after a bounce passing on weight of the light, or None if it
+    // ends here
+    fn bounce(&self, lobe: Lobe, weight: Color, limits: &PathLimits) -> Option<Self> {
+        let mut next = *self;
+        next.depth += 1;
+        next.bounces[lobe as usize] += 1;
+        next.throughput = self.throughput * weight;
+
+        let n = next.bounces[lobe as usize];
+        let lobe_limits = limits.of(lobe);
+        if next.depth >= limits.max_depth || n > lobe_limits.max {
+            return None;
+        }
+        if n <= lobe_limits.min {
+            return Some(next);
+        }
+
+        // carry on with the throughput's odds; the paths that do make up for
+        // the ones that don't
+        let t = next.throughput;
+        let q = t.r().max(t.g()).max(t.b()).min(1.0);
+        if random::double() >= q {
+            return None;
+        }
+        next.throughput /= q;
+        Some(next)
+    }
+}
+
+// Where a path went, for debugging
+struct PathVertex {
+    p: Point3,
+    // how it left, or None if it ended there. Paths that escape the scene
+    // or are cut short just stop after their last vertex.
+    lobe: Option<Lobe>,
+    // on arrival
+    throughput: Color,
+}
+
+fn record(vertices: &mut Option<&mut Vec<PathVertex>>, p: Point3, lobe: Option<Lobe>,
+          path: &PathState) {
+    if let Some(v) = vertices {
+        v.push(PathVertex { p, lobe, throughput: path.throughput });
+    }
+}
+
+// Follow a path from the camera, adding up the light it finds. The vertices
+// it visits are recorded, if there's somewhere to put them.
+fn ray_color(r: Ray,
              scene: &Scene,
-             depth: i32) -> Color {
-    if depth <= 0 {
-        return Color(0., 0., 0.);
-    }
-    if let Some(hr) = scene.world.hit(r, 0.001, INFINITY) {
-        let emitted = hr.mat.emitted(r, &hr, hr.u, hr.v, hr.p);
-        if let Some(mut sr) =  hr.mat.scatter(r, &hr) {
-            if let Some(spec_r) = sr.specular_ray {
-                return sr.attenuation
-                    * ray_color(&spec_r, scene, depth - 1);
+             stack: &mut MediumStack,
+             tracer: &Tracer,
+             mut vertices: Option<&mut Vec<PathVertex>>) -> Radiance {
+    let n_lights = tracer.n_lights;
+    let mut rad = Radiance::zero(n_lights);
+    let mut path = PathState::new();
+    let mut r = r;
+
+    loop {
+        // In spectral mode, colors are evaluated at the path's wavelengths
+        let wavelengths = r.wavelengths;
+        let lift = |c: Color| wavelengths.map_or(c, |w| w.spectrum(c));
+
+        let hit = scene.world.hit(&r, 0.001, INFINITY);
+
+        // Inside a volume, the ray may scatter before it reaches the next surface
+        let (hit, beta) = if let Some(medium) = stack.medium() {
+            let t_max = hit.as_ref().map_or(INFINITY, |hr| hr.t);
+            let (scatter, beta) = medium.sample(&r, t_max);
+            (scatter.or(hit), beta)
+        } else {
+            (hit, Color(1.0, 1.0, 1.0))
+        };
+        path.throughput = path.throughput * beta;
+
+        let Some(mut hr) = hit else {
+            // TODO(oren): this doesn't make a great deal of sense
+            rad.add_emission(&path, lift(scene.background), None);
+            break;
+        };
+
+        if !true_hit(stack, &r, &mut hr) {
+            r = Ray::new(hr.p, r.dir, r.time).with_wavelengths(wavelengths);
+            continue;
+        }
+
+        let emitted = lift(hr.mat.emitted(&r, &hr, hr.u, hr.v, hr.p));
+        let light = if n_lights > 0 && !emitted.near_zero() {
+            light_index(scene, &r, &hr)
+        } else {
+            None
+        };
+        let Some(mut sr) = hr.mat.scatter(&r, &hr) else {
+            rad.add_emission(&path, emitted, light);
+            record(&mut vertices, hr.p, None, &path);
+            break;
+        };
+
+        let mut wavelengths = wavelengths;
+        let mut secondary = Color(1.0, 1.0, 1.0);
+        if hr.mat.dispersive(&r, &hr) {
+            if let Some(w) = wavelengths.as_mut() {
+                secondary = w.terminate_secondary();
             }
-            let light_pdf = if scene.lights.empty() {
-                sr.pdf.clone()
-            } else {
-                HittablePDF::new(scene.lights.clone(), hr.p).into()
-            };
-            let mix_pdf = MixturePDF::new(light_pdf.clone(), sr.pdf.clone());
-            let scattered = Ray::new(hr.p, mix_pdf.generate(&mut sr), r.time);
-            let pdf_val = mix_pdf.value(scattered.dir);
+        }
+        let spectral = sr.spectral.unwrap_or(Color(1.0, 1.0, 1.0));
 
-            assert!(pdf_val > 0.0, "PDF val {:4} < 0; p: {}", pdf_val, hr.p);
+        if let Some(spec_r) = sr.specular_ray {
+            let lobe = Lobe::of(&r, &hr, spec_r.dir, true);
+            record(&mut vertices, hr.p, Some(lobe), &path);
+            let weight = lift(sr.attenuation) * spectral * secondary;
+            let Some(next) = path.bounce(lobe, weight, &tracer.limits) else { break; };
+            update_media(stack, &hr, spec_r.dir);
+            path = next;
+            r = spec_r.with_wavelengths(wavelengths);
+            continue;
+        }
 
-            emitted +
-                sr.attenuation *
-                hr.mat.scattering_pdf(r, &hr, &scattered) *
-                ray_color(&scattered, scene, depth-1) /
-                pdf_val
+        rad.add_emission(&path, emitted, light);
 
+        let light_pdf = if scene.lights.empty() {
+            sr.pdf.clone()
         } else {
-            emitted
+            HittablePDF::new(scene.lights.clone(), hr.p).into()
+        };
+        let mix_pdf = MixturePDF::new(light_pdf.clone(), sr.pdf.clone());
+        let scattered = Ray::new(hr.p, mix_pdf.generate(&mut sr), r.time)
+            .with_wavelengths(wavelengths);
+        let pdf_val = mix_pdf.value(scattered.dir);
+
+        // a direction that couldn't have been sampled: nothing sensible to
+        // weight it by, so end the path here
+        if pdf_val.is_nan() || pdf_val <= 0.0 || pdf_val.is_infinite() {
+            tracer.bad_pdfs.fetch_add(1, Ordering::Relaxed);
+            record(&mut vertices, hr.p, None, &path);
+            break;
         }
-    } else {
-        // TODO(oren): this doesn't make a great deal of sense
-        scene.background
+
+        // NOTE: generate may have updated sr.attenuation
+        let value = hr.mat.scattering_value(&r, &hr, sr.attenuation, &scattered);
+        let weight = lift(value) * spectral * secondary / pdf_val;
+
+        let lobe = Lobe::of(&r, &hr, scattered.dir, false);
+        record(&mut vertices, hr.p, Some(lobe), &path);
+        let Some(next) = path.bounce(lobe, weight, &tracer.limits) else { break; };
+        update_media(stack, &hr, scattered.dir);
+        path = next;
+        r = scattered;
     }
+    rad
 }
 
 #[derive(Parser, Debug)]
@@ -72,26 +408,339 @@ struct Cli {
     #[arg(short, long, default_value_t = 3)]
     scene: usize,
 
-    /// Output file (stdout if omitted)
+    /// Render spectrally (hero wavelength sampling) instead of in RGB
+    #[arg(long)]
+    spectral: bool,
+
+    /// How to find the light: paths from the camera, bidirectional paths
+    /// from the camera and the lights joined up, or progressive photon
+    /// mapping (the last two are better for caustics and indirectly lit
+    /// scenes; RGB only, no media)
+    #[arg(long, value_enum, default_value_t = Integrator::Path)]
+    integrator: Integrator,
+
+    /// Photons sent out per pass of --integrator ppm (default: one per pixel)
+    #[arg(long)]
+    photons: Option<usize>,
+
+    /// Radius photons are gathered from at first, which shrinks with each
+    /// pass (default: 1/200 of the scene's size)
+    #[arg(long)]
+    photon_radius: Option<f64>,
+
+    /// Most bounces a path can take
+    #[arg(long, default_value_t = 50)]
+    max_depth: u32,
+
+    /// Diffuse bounces, including scattering in media, before Russian
+    /// roulette can end a path, and the most it can take, as MIN:MAX
+    #[arg(long, value_parser = parse_depths, default_value = "3:50")]
+    diffuse_depth: DepthLimits,
+
+    /// Same, for mirror-like reflection
+    #[arg(long, value_parser = parse_depths, default_value = "3:50")]
+    specular_depth: DepthLimits,
+
+    /// Same, for light passing through surfaces
+    #[arg(long, value_parser = parse_depths, default_value = "3:50")]
+    transmission_depth: DepthLimits,
+
+    /// Most that indirect light can add to a channel of one sample, to keep
+    /// down fireflies (at the cost of some bias)
+    #[arg(long)]
+    clamp: Option<f64>,
+
+    /// Print the vertices of a path through pixel X,Y (from the top left),
+    /// for debugging
+    #[arg(long, value_parser = parse_pixel)]
+    trace_path: Option<(i32, i32)>,
+
+    /// Output file (stdout if omitted). With --frames, the frame number is
+    /// added to the name, e.g. out.ppm -> out_0012.ppm
     #[arg(short, long)]
     outfile: Option<std::path::PathBuf>,
+
+    /// Also write render passes (beauty, emission, direct and indirect
+    /// light, depth, position, normals, UV, albedo, material and object IDs
+    /// and each light) to this multi-layer OpenEXR file. Numbered like the
+    /// output with --frames.
+    #[arg(long)]
+    passes: Option<std::path::PathBuf>,
+
+    /// Denoise the image, guided by the albedo, normal and depth passes.
+    /// The passes keep the noisy image as beauty and add it as denoised.
+    #[arg(long)]
+    denoise: bool,
+
+    /// Passes of the denoising filter; each reaches twice as far
+    #[arg(long, default_value_t = 5)]
+    denoise_iterations: u32,
+
+    /// Don't render; denoise the beauty from a file written by --passes
+    /// instead, writing the image to --outfile (and the passes, with the
+    /// denoised layer added, to --passes)
+    #[arg(long)]
+    denoise_passes: Option<std::path::PathBuf>,
+
+    /// Render an animation: frames FIRST:LAST (inclusive)
+    #[arg(long, value_parser = parse_frames, requires = "outfile")]
+    frames: Option<(u32, u32)>,
+
+    /// Animation frames per second
+    #[arg(long, default_value_t = 24.0)]
+    fps: f64,
+
+    /// How long the shutter stays open, as a fraction of a frame (unless
+    /// --shutter-speed says otherwise)
+    #[arg(long, default_value_t = 0.5)]
+    shutter: f64,
+
+    /// How the shutter opens and closes, which shapes the motion blur
+    #[arg(long, value_enum, default_value_t = ShutterCurveArg::Box)]
+    shutter_curve: ShutterCurveArg,
+
+    /// Custom shutter curve: how open the shutter is at evenly spaced times
+    /// from opening to closing, e.g. 0,1,1,0.5,0 (overrides --shutter-curve)
+    #[arg(long, value_parser = parse_profile)]
+    shutter_profile: Option<Numbers>,
+
+    /// Rolling shutter: fraction of the open time spent reading out the
+    /// rows, top to bottom (0 for a global shutter)
+    #[arg(long, default_value_t = 0.0)]
+    rolling_shutter: f64,
+
+    /// Sensor sensitivity. Setting any of --iso, --shutter-speed or --f-stop
+    /// turns on photographic exposure; ISO 100, 1/100s at f/16 leaves the
+    /// image as it is.
+    #[arg(long)]
+    iso: Option<f64>,
+
+    /// Exposure time in seconds (default 1/100, or --shutter / --fps for
+    /// animations). Also how long the shutter is open for motion blur,
+    /// overriding --shutter, with scene time in seconds.
+    #[arg(long)]
+    shutter_speed: Option<f64>,
+
+    /// Aperture as an f-number for a full frame camera at the current field
+    /// of view (replaces --aperture)
+    #[arg(long)]
+    f_stop: Option<f64>,
+
+    /// Scene units per meter, to size the aperture for --f-stop
+    #[arg(long, default_value_t = 1.0)]
+    units_per_meter: f64,
+
+    /// Camera projection
+    #[arg(long, value_enum, default_value_t = ProjectionArg::Perspective)]
+    projection: ProjectionArg,
+
+    /// Field of view in degrees, overriding the scene's. For fisheyes it's
+    /// across the image circle, and defaults to 180.
+    #[arg(long)]
+    fov: Option<f64>,
+
+    /// Camera up direction, as X,Y,Z
+    #[arg(long, value_parser = parse_triple, default_value = "0,1,0")]
+    vup: (f64, f64, f64),
+
+    /// Distance to the plane of focus (default 10, or the scene's)
+    #[arg(long)]
+    focus_dist: Option<f64>,
+
+    /// Focus on whatever is at this point in the image, as S,T from 0 to 1
+    /// with 0,0 at the bottom left (e.g. 0.5,0.5 for the middle)
+    #[arg(long, value_parser = parse_pair)]
+    autofocus: Option<(f64, f64)>,
+
+    /// Number of aperture blades, for polygonal bokeh (round if omitted)
+    #[arg(long)]
+    blades: Option<u32>,
+
+    /// Rotation of the aperture blades, in degrees
+    #[arg(long, default_value_t = 0.0)]
+    blade_rotation: f64,
+
+    /// Image to use as the aperture shape (brighter is more open)
+    #[arg(long)]
+    aperture_image: Option<std::path::PathBuf>,
+
+    /// Optical vignetting ("cat's eye" bokeh toward the edges), 0 to 1
+    #[arg(long, default_value_t = 0.0)]
+    cat_eye: f64,
+
+    /// Lens tilt in degrees, tilting the plane of focus about the horizontal
+    #[arg(long, default_value_t = 0.0)]
+    tilt: f64,
+
+    /// Lens swing in degrees, tilting the plane of focus about the vertical
+    #[arg(long, default_value_t = 0.0)]
+    swing: f64,
+
+    /// Lens shift as X,Y fractions of the image size
+    #[arg(long, value_parser = parse_pair, default_value = "0,0")]
+    shift: (f64, f64),
+
+    /// Render a stereo pair. With --projection equirect, an omni-directional
+    /// stereo (ODS) panorama.
+    #[arg(long, value_enum)]
+    stereo: Option<ConvergenceArg>,
+
+    /// How the two eyes are arranged in the image
+    #[arg(long, value_enum, default_value_t = LayoutArg::SideBySide)]
+    stereo_layout: LayoutArg,
+
+    /// Distance between the eyes, in scene units (default: 1/30 of the
+    /// focus distance)
+    #[arg(long)]
+    interocular: Option<f64>,
 }
 
-#[allow(clippy::too_many_lines)]
-fn main() {
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Integrator {
+    /// Unidirectional path tracing, with next event estimation
+    Path,
+    /// Bidirectional path tracing
+    Bdpt,
+    /// Stochastic progressive photon mapping, a pass per sample
+    Ppm,
+}
 
-    let args = Cli::parse();
-    const MAX_DEPTH : i32 = 50;
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ConvergenceArg {
+    /// Parallel eyes, images shifted to converge at the focus distance
+    Parallel,
+    /// Eyes turned in to converge at the focus distance
+    ToeIn,
+}
 
-    // Camera
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ShutterCurveArg {
+    /// Fully open the whole time
+    Box,
+    /// Opens and closes linearly
+    Triangle,
+}
 
-    let aperture = args.aperture;
-    let aspect_ratio = args.aspect_ratio;
-    let image_width = args.width;
-    let samples_per_pixel = args.samples;
-    let scene_select = args.scene;
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum LayoutArg {
+    SideBySide,
+    TopBottom,
+}
+
+fn parse_numbers(s: &str, n: usize) -> Result<Vec<f64>, String> {
+    let vals = s.split(',').map(|v| {
+        v.trim().parse::<f64>().map_err(|e| format!("bad number '{v}': {e}"))
+    }).collect::<Result<Vec<f64>, String>>()?;
+    if vals.len() == n {
+        Ok(vals)
+    } else {
+        Err(format!("expected {n} comma separated numbers"))
+    }
+}
+
+// A comma separated list, parsed as one argument (clap would take a Vec as
+// several)
+type Numbers = Vec<f64>;
+
+fn parse_list(s: &str) -> Result<Numbers, String> {
+    parse_numbers(s, s.split(',').count())
+}
+
+// What ShutterProfile::new accepts
+fn parse_profile(s: &str) -> Result<Numbers, String> {
+    let values = parse_list(s)?;
+    if values.len() < 2 {
+        return Err("expected at least two values".into());
+    }
+    if values.iter().any(|v| !v.is_finite() || *v < 0.0) {
+        return Err("values must be finite and not negative".into());
+    }
+    if !values.iter().any(|v| *v > 0.0) {
+        return Err("the shutter never opens".into());
+    }
+    Ok(values)
+}
+
+fn parse_pair(s: &str) -> Result<(f64, f64), String> {
+    let v = parse_numbers(s, 2)?;
+    Ok((v[0], v[1]))
+}
+
+fn parse_triple(s: &str) -> Result<(f64, f64, f64), String> {
+    let v = parse_numbers(s, 3)?;
+    Ok((v[0], v[1], v[2]))
+}
 
-    let scene = match scene_select {
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum ProjectionArg {
+    Perspective,
+    Orthographic,
+    /// Equidistant fisheye
+    Fisheye,
+    /// Equisolid angle (equal area) fisheye
+    FisheyeEquisolid,
+    /// 360 degree panorama (use -r 2)
+    Equirect,
+    /// Six cube faces in a 3x2 grid (use -r 1.5)
+    Cubemap,
+}
+
+impl From<ProjectionArg> for Projection {
+    fn from(p: ProjectionArg) -> Projection {
+        match p {
+            ProjectionArg::Perspective => Projection::Perspective,
+            ProjectionArg::Orthographic => Projection::Orthographic,
+            ProjectionArg::Fisheye => Projection::Fisheye { equisolid: false },
+            ProjectionArg::FisheyeEquisolid => Projection::Fisheye { equisolid: true },
+            ProjectionArg::Equirect => Projection::Equirectangular,
+            ProjectionArg::Cubemap => Projection::Cubemap,
+        }
+    }
+}
+
+fn parse_depths(s: &str) -> Result<DepthLimits, String> {
+    let (min, max) = s.split_once(':').ok_or("expected MIN:MAX")?;
+    let min: u32 = min.parse().map_err(|e| format!("bad min depth: {e}"))?;
+    let max: u32 = max.parse().map_err(|e| format!("bad max depth: {e}"))?;
+    if max < min {
+        return Err(format!("max depth {max} is less than min depth {min}"));
+    }
+    Ok(DepthLimits { min, max })
+}
+
+fn parse_pixel(s: &str) -> Result<(i32, i32), String> {
+    let (x, y) = s.split_once(',').ok_or("expected X,Y")?;
+    let coord = |c: &str| match c.trim().parse::<u16>() {
+        Ok(c) => Ok(i32::from(c)),
+        Err(e) => Err(format!("bad pixel coordinate '{c}': {e}")),
+    };
+    Ok((coord(x)?, coord(y)?))
+}
+
+fn parse_frames(s: &str) -> Result<(u32, u32), String> {
+    let (first, last) = s.split_once(':').unwrap_or((s, s));
+    let first: u32 = first.parse().map_err(|e| format!("bad first frame: {e}"))?;
+    let last: u32 = last.parse().map_err(|e| format!("bad last frame: {e}"))?;
+    if last < first {
+        return Err(format!("last frame {last} is before first frame {first}"));
+    }
+    Ok((first, last))
+}
+
+// out.ppm -> out_0012.ppm
+fn frame_path(path: &std::path::Path, frame: u32) -> std::path::PathBuf {
+    let stem = path.file_stem().map_or_else(
+        || "frame".into(), |s| s.to_string_lossy().into_owned()
+    );
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:04}.{}", stem, frame, ext.to_string_lossy()),
+        None => format!("{stem}_{frame:04}"),
+    };
+    path.with_file_name(name)
+}
+
+fn static_scene(scene_select: usize) -> Scene {
+    match scene_select {
         1 => scene::defs::random_scene(),
         2 => scene::defs::two_spheres(),
         3 =>scene::defs::cornell_sphere(),
@@ -113,56 +762,544 @@ fn main() {
         17 => scene::defs::purple_flower(),
         18 => scene::defs::knob1(),
         19 => scene::defs::knob2(),
+        20 => scene::defs::cornell_cloud(),
+        21 => scene::defs::foggy_lights(),
+        22 => scene::defs::nested_dielectrics(),
+        23 => scene::defs::subsurface_spheres(),
+        24 => scene::defs::dispersion(),
+        25 => scene::defs::thin_film(),
+        26 => scene::defs::clearcoat(),
+        27 => scene::defs::material_blend(),
+        28 => scene::defs::rough_diffuse(),
+        29 => scene::defs::fabric(),
+        30 => scene::defs::measured_brdf("data/merl/blue-metallic-paint.binary"),
+        31 => scene::defs::hair(),
+        32 => scene::defs::instancing(),
+        33 => scene::defs::motion_blur(),
         _ => scene::defs::final_scene()
-    };
+    }
+}
 
-    #[allow(clippy::cast_possible_truncation)]
-    let image_height : i32 = ((f64::from(image_width)) / aspect_ratio) as i32;
-    let vup = Vec3(0., 1., 0.);
-    let dist_to_focus = 10.0;
+fn print_path(x: i32, y: i32, vertices: &[PathVertex]) {
+    eprintln!("\nPath through pixel {x},{y}:");
+    for (i, v) in vertices.iter().enumerate() {
+        let how = v.lobe.map_or_else(|| "end".to_string(), |l| format!("{l:?}"));
+        eprintln!("  {i}: {} {how}, throughput {}", v.p, v.throughput);
+    }
+}
 
-    let cam =
-        Camera::new(&scene, vup, aspect_ratio, aperture, dist_to_focus, 0.0, 1.0);
+// Things about the image that don't change from frame to frame
+#[derive(Copy, Clone)]
+struct RenderSettings {
+    image_width: i32,
+    image_height: i32,
+    samples_per_pixel: i32,
+    spectral: bool,
+    integrator: Integrator,
+    // for photon mapping
+    photons: Option<usize>,
+    photon_radius: Option<f64>,
+    limits: PathLimits,
+    // print out a path through this pixel (x, y from the top left)
+    trace_pixel: Option<(i32, i32)>,
+    // brightness from the photographic exposure
+    exposure: f64,
+}
 
-    let mut stdout = if let Some(fname) = args.outfile {
-        Box::new(
-            BufWriter::new(File::create(fname).expect("Unable to open file"))
-        ) as Box<dyn Write>
-    } else {
-        Box::new(
-            BufWriter::new(std::io::stdout().lock())
-        ) as Box<dyn Write>
-    };
+// What a camera ray sees first, for the data passes
+#[derive(Copy, Clone)]
+struct Surface {
+    t: f64,
+    p: Point3,
+    norm: Vec3,
+    shading_norm: Vec3,
+    uv: Vec3,
+    albedo: Color,
+    // numbered from 1 in the order the scene hands them out, so they're the
+    // same from run to run and frame to frame; 0 if unknown
+    material_id: f64,
+    object_id: f64,
+}
+
+// Number the scene's materials in the order its objects give them
+fn material_ids(scene: &Scene) -> HashMap<usize, u32> {
+    let mut ids = HashMap::new();
+    scene.world.materials(&mut |m| {
+        let next = ids.len() as u32 + 1;
+        ids.entry(Arc::as_ptr(m).cast::<()>() as usize).or_insert(next);
+    });
+    ids
+}
+
+impl Surface {
+    // Surfaces the path tracer would pass straight through (see true_hit)
+    // are skipped here too
+    fn first(r: &Ray, scene: &Scene, material_ids: &HashMap<usize, u32>) -> Option<Self> {
+        let mut stack = MediumStack::new();
+        let mut r = Ray::new(r.origin, r.dir, r.time);
+        let hr = loop {
+            let mut hr = scene.world.hit(&r, 0.001, INFINITY)?;
+            if true_hit(&mut stack, &r, &mut hr) {
+                break hr;
+            }
+            r = Ray::new(hr.p, r.dir, r.time);
+        };
+        // NOTE: generate may update sr.attenuation, so take it from there,
+        // as the integrator does
+        let albedo = hr.mat.scatter(&r, &hr).map_or(Color(0.0, 0.0, 0.0), |mut sr| {
+            if sr.specular_ray.is_none() {
+                sr.pdf.clone().generate(&mut sr);
+            }
+            sr.attenuation
+        });
+        let material_id = material_ids.get(&(Arc::as_ptr(&hr.mat).cast::<()>() as usize));
+        Some(Self {
+            t: hr.t,
+            p: hr.p,
+            norm: hr.norm,
+            shading_norm: hr.shading_geo.n,
+            uv: Vec3(hr.u, hr.v, 0.0),
+            albedo,
+            material_id: f64::from(material_id.copied().unwrap_or(0)),
+            object_id: f64::from(hr.object_id),
+        })
+    }
+}
+
+// A pixel's samples, summed up for the output passes
+struct Pixel {
+    radiance: Radiance,
+    // data passes, over the samples that hit something
+    hits: u32,
+    t: f64,
+    p: Point3,
+    norm: Vec3,
+    shading_norm: Vec3,
+    uv: Vec3,
+    albedo: Color,
+    // IDs don't average, so they're the first sample's
+    material_id: Option<f64>,
+    object_id: Option<f64>,
+}
+
+impl Pixel {
+    fn new(n_lights: usize) -> Self {
+        Self {
+            radiance: Radiance::zero(n_lights),
+            hits: 0,
+            t: 0.0,
+            p: Vec3::new(),
+            norm: Vec3::new(),
+            shading_norm: Vec3::new(),
+            uv: Vec3::new(),
+            albedo: Color(0.0, 0.0, 0.0),
+            material_id: None,
+            object_id: None,
+        }
+    }
+
+    fn add_surface(&mut self, s: &Surface) {
+        self.hits += 1;
+        self.t += s.t;
+        self.p += s.p;
+        self.norm += s.norm;
+        self.shading_norm += s.shading_norm;
+        self.uv += s.uv;
+        self.albedo += s.albedo;
+        self.material_id.get_or_insert(s.material_id);
+        self.object_id.get_or_insert(s.object_id);
+    }
+
+    // Fill in pixel (x, y) of the film's layers, as laid out by film_layers
+    fn store(&self, film: &mut Film, x: usize, y: usize, samples_per_pixel: i32,
+             passes: bool) {
+        let scale = 1.0 / f64::from(samples_per_pixel);
+        let rgb = |c: Color| [scale * c.r(), scale * c.g(), scale * c.b()];
+        let rad = &self.radiance;
+        film.set(0, x, y, &rgb(rad.total()));
+        if !passes {
+            return;
+        }
+        film.set(1, x, y, &rgb(rad.emitted));
+        film.set(2, x, y, &rgb(rad.direct));
+        film.set(3, x, y, &rgb(rad.indirect));
+
+        let n = f64::from(self.hits.max(1));
+        let avg = |v: Vec3| [v.x() / n, v.y() / n, v.z() / n];
+        film.set(4, x, y, &[if self.hits > 0 { self.t / n } else { INFINITY }]);
+        film.set(5, x, y, &avg(self.p));
+        film.set(6, x, y, &avg(self.norm));
+        film.set(7, x, y, &avg(self.shading_norm));
+        film.set(8, x, y, &avg(self.uv)[..2]);
+        film.set(9, x, y, &avg(self.albedo));
+        film.set(10, x, y, &[self.material_id.unwrap_or(0.0)]);
+        film.set(11, x, y, &[self.object_id.unwrap_or(0.0)]);
+        for (l, c) in rad.lights.iter().enumerate() {
+            film.set(12 + l, x, y, &rgb(*c));
+        }
+    }
+}
+
+// Just the beauty, unless the passes are wanted too
+fn film_layers(width: usize, height: usize, passes: bool, n_lights: usize) -> Film {
+    let mut film = Film::new(width, height);
+    film.add_layer("beauty", &["R", "G", "B"]);
+    if !passes {
+        return film;
+    }
+    for name in ["emission", "direct", "indirect"] {
+        film.add_layer(name, &["R", "G", "B"]);
+    }
+    film.add_layer("depth", &["Z"]);
+    film.add_layer("position", &["X", "Y", "Z"]);
+    film.add_layer("normal", &["X", "Y", "Z"]);
+    film.add_layer("shading_normal", &["X", "Y", "Z"]);
+    film.add_layer("uv", &["U", "V"]);
+    film.add_layer("albedo", &["R", "G", "B"]);
+    film.add_layer("material_id", &["id"]);
+    film.add_layer("object_id", &["id"]);
+    for l in 0..n_lights {
+        film.add_layer(&format!("light{l}"), &["R", "G", "B"]);
+    }
+    film
+}
+
+// Render one image, with all the passes if wanted
+fn render(scene: &Scene, cam: &(dyn CameraModel + Sync + Send), settings: &RenderSettings,
+          passes: bool) -> Film {
+    let RenderSettings {
+        image_width, image_height, samples_per_pixel, spectral, integrator, limits,
+        trace_pixel, exposure, ..
+    } = *settings;
 
-    // Render
     // let mut stderr = BufWriter::new(std::io::stderr().lock());
     let mut stderr = BufWriter::new(std::io::stderr());
 
-    writeln!(stdout, "P3");
-    writeln!(stdout, "{} {}", image_width, image_height);
-    writeln!(stdout, "255");
+    let n_lights = if passes { scene.lights.objects.len() } else { 0 };
+    let tracer = Tracer {
+        limits,
+        n_lights,
+        bad_samples: AtomicUsize::new(0),
+        bad_pdfs: AtomicUsize::new(0),
+    };
+    #[allow(clippy::cast_sign_loss)]
+    let (width, height) = (image_width as usize, image_height as usize);
+    let mut film = film_layers(width, height, passes, n_lights);
+    let splats = bdpt::Splats::new(width, height, n_lights);
+    let material_ids = if passes { material_ids(scene) } else { HashMap::new() };
 
-    for j in (0..image_height).rev() {
+    // BDPT lands light anywhere on the image, so nothing's stored until the
+    // end. Photon mapping follows its own camera paths, a pass at a time, so
+    // it only needs these for the passes.
+    let scanlines = if integrator == Integrator::Ppm && !passes { 0 } else { image_height };
+    let mut rows = Vec::with_capacity(height);
+    for j in (0..scanlines).rev() {
         write!(stderr, "\rScanlines remaining: {} ", j);
         stderr.flush();
-        let colors : Vec<Color> = (0..image_width).into_par_iter().map(|i| {
-            let mut pixel_color = Color(0., 0., 0.);
+        let pixels : Vec<Pixel> = (0..image_width).into_par_iter().map(|i| {
+            let mut pixel = Pixel::new(n_lights);
+            let mut traced = false;
             for s in (0..samples_per_pixel) {
+                // pixels tile the image exactly, so BDPT's splats land back
+                // on the pixels whose rays would have found them
                 let u : f64 =
-                    (f64::from(i) + random::double()) / f64::from(image_width - 1);
+                    (f64::from(i) + random::double()) / f64::from(image_width);
                 let v : f64 =
-                    (f64::from(j) + random::double()) / f64::from(image_height - 1);
+                    (f64::from(j) + random::double()) / f64::from(image_height);
 
-                let r = cam.get_ray(u, v);
-                pixel_color += ray_color(&r, &scene, MAX_DEPTH);
+                let Some(r) = cam.ray(u, v) else { continue; };
+                // the first path through trace_pixel gets printed
+                let mut trace = (!traced && trace_pixel == Some((i, image_height - 1 - j)))
+                    .then(Vec::new);
+                if passes {
+                    if let Some(surface) = Surface::first(&r, scene, &material_ids) {
+                        pixel.add_surface(&surface);
+                    }
+                }
+                if integrator == Integrator::Ppm {
+                    // lit by the photon map, once all the pixels are known
+                    continue;
+                }
+                let rad = if integrator == Integrator::Bdpt {
+                    bdpt::bdpt(r, scene, cam, &tracer, &splats)
+                } else if spectral {
+                    let w = Wavelengths::sample();
+                    let r = r.with_wavelengths(Some(w));
+                    ray_color(r, scene, &mut MediumStack::new(), &tracer, trace.as_mut())
+                        .map(|c| w.to_rgb(c))
+                } else {
+                    ray_color(r, scene, &mut MediumStack::new(), &tracer, trace.as_mut())
+                };
+                if let Some(vertices) = trace.take() {
+                    print_path(i, image_height - 1 - j, &vertices);
+                    traced = true;
+                }
+                let total = rad.total();
+                if total.is_nan() || !(total.r() + total.g() + total.b()).is_finite() {
+                    tracer.bad_samples.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                let rad = match limits.clamp {
+                    Some(max) => rad.clamp_indirect(max),
+                    None => rad,
+                };
+                pixel.radiance = pixel.radiance.add(&rad);
             };
-            pixel_color
+            pixel
         }).collect();
-        for pc in &colors {
-            write_color(&mut stdout, *pc, samples_per_pixel);
+        rows.push(pixels);
+    }
+    if scanlines > 0 {
+        write!(stderr, "\nDone\n");
+    }
+
+    let photon_mapped = (integrator == Integrator::Ppm)
+        .then(|| sppm::render(scene, cam, settings, &tracer));
+    rows.resize_with(height, || (0..width).map(|_| Pixel::new(n_lights)).collect());
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            if let Some(rad) = &photon_mapped {
+                pixel.radiance = rad[y * width + x].clone();
+            } else if integrator == Integrator::Bdpt {
+                pixel.radiance = pixel.radiance.clone().add(&splats.get(x, y));
+            }
+            pixel.radiance = pixel.radiance.clone().scale(Color(exposure, exposure, exposure));
+            pixel.store(&mut film, x, y, samples_per_pixel, passes);
+        }
+    }
+
+    let bad_samples = tracer.bad_samples.load(Ordering::Relaxed);
+    if bad_samples > 0 {
+        eprintln!("Discarded {bad_samples} NaN or infinite samples");
+    }
+    let bad_pdfs = tracer.bad_pdfs.load(Ordering::Relaxed);
+    if bad_pdfs > 0 {
+        eprintln!("Ended {bad_pdfs} paths on a zero pdf");
+    }
+    film
+}
+
+// Write one of the film's RGB layers out as PPM
+fn write_ppm<W: Write>(out: &mut W, film: &Film, layer: &str) {
+    let pixels = film.pixels(layer, &["R", "G", "B"]).expect("No such layer");
+    writeln!(out, "P3");
+    writeln!(out, "{} {}", film.width(), film.height());
+    writeln!(out, "255");
+    for pc in pixels {
+        write_color(out, pc, 1);
+    }
+}
+
+fn main() {
+
+    let args = Cli::parse();
+
+    // Camera
+
+    let aperture = args.aperture;
+    let aspect_ratio = args.aspect_ratio;
+    let image_width = args.width;
+    let samples_per_pixel = args.samples;
+    let scene_select = args.scene;
+    let spectral = args.spectral;
+    if spectral && args.integrator != Integrator::Path {
+        eprintln!("Only the path integrator renders spectrally; leave out --spectral");
+        std::process::exit(1);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    let image_height : i32 = ((f64::from(image_width)) / aspect_ratio) as i32;
+    let vup = Vec3(args.vup.0, args.vup.1, args.vup.2);
+    let dist_to_focus = args.focus_dist.unwrap_or(10.0);
+
+    let settings = RenderSettings {
+        image_width, image_height, samples_per_pixel, spectral,
+        integrator: args.integrator,
+        photons: args.photons,
+        photon_radius: args.photon_radius,
+        limits: PathLimits {
+            max_depth: args.max_depth,
+            diffuse: args.diffuse_depth,
+            specular: args.specular_depth,
+            transmission: args.transmission_depth,
+            clamp: args.clamp,
+        },
+        trace_pixel: args.trace_path,
+        exposure: 1.0,
+    };
+
+    let open = |fname: Option<std::path::PathBuf>| {
+        if let Some(fname) = fname {
+            Box::new(
+                BufWriter::new(File::create(fname).expect("Unable to open file"))
+            ) as Box<dyn Write>
+        } else {
+            Box::new(
+                BufWriter::new(std::io::stdout().lock())
+            ) as Box<dyn Write>
+        }
+    };
+
+    let denoiser = Denoiser {
+        iterations: args.denoise_iterations,
+        ..Denoiser::default()
+    };
+
+    // Denoise if asked, then write out the image, and the passes if wanted
+    let finish = |mut film: Film, denoise: bool, fname: Option<std::path::PathBuf>,
+                  passes: Option<std::path::PathBuf>| {
+        let layer = if denoise {
+            let denoised = denoiser.denoise(&film, "beauty")
+                .expect("Denoising needs the beauty, albedo, normal and depth passes");
+            film.set_layer("denoised", &["R", "G", "B"], &denoised);
+            "denoised"
+        } else {
+            "beauty"
+        };
+        write_ppm(&mut open(fname), &film, layer);
+        if let Some(path) = passes {
+            film.write_exr(&path).expect("Unable to write passes");
+        }
+    };
+
+    if let Some(fname) = &args.denoise_passes {
+        let film = Film::read_exr(fname).expect("Unable to read passes");
+        finish(film, true, args.outfile.clone(), args.passes.clone());
+        return;
+    }
+    let passes = args.passes.is_some() || args.denoise;
+
+    let anim = match scene_select {
+        34 => scene::anims::turntable(),
+        35 => scene::anims::hopping_cube(),
+        n => AnimatedScene::still(static_scene(n), dist_to_focus),
+    };
+
+    let projection = Projection::from(args.projection);
+    let lens = Lens {
+        aperture,
+        shape: match (&args.aperture_image, args.blades) {
+            (Some(fname), _) => ApertureImage::new(fname).into(),
+            (None, Some(blades)) => Aperture::Polygon {
+                blades, rotation: args.blade_rotation,
+            },
+            (None, None) => Aperture::Disk,
+        },
+        cat_eye: args.cat_eye,
+        tilt: args.tilt,
+        swing: args.swing,
+        shift: args.shift,
+    };
+
+    let curve = match &args.shutter_profile {
+        Some(values) => ShutterProfile::new(values).into(),
+        None => match args.shutter_curve {
+            ShutterCurveArg::Box => ShutterCurve::Box,
+            ShutterCurveArg::Triangle => ShutterCurve::Triangle,
+        },
+    };
+    let shutter = |time0: f64, time1: f64| Shutter {
+        open: time0,
+        close: time1,
+        curve: curve.clone(),
+        rolling: args.rolling_shutter,
+    };
+
+    let exposing = args.iso.is_some() || args.shutter_speed.is_some()
+        || args.f_stop.is_some();
+    let default_speed = if args.frames.is_some() { args.shutter / args.fps } else { 0.01 };
+    // A shutter speed sets how long the shutter is open as well as how much
+    // light gets in; without one, stills are open from 0 to 1 as ever
+    let open_for = |default: f64| args.shutter_speed.unwrap_or(default);
+
+    // The lens to use for a view, and how bright that makes the image. With
+    // photographic exposure the f-stop and aperture go together: whichever
+    // was given sets the other.
+    let expose = |view: &CameraView| -> (Lens, f64) {
+        if !exposing {
+            return (lens.clone(), 1.0);
+        }
+        let focal_length = Exposure::focal_length(view.vfov) * args.units_per_meter;
+        let f_stop = match args.f_stop {
+            Some(f_stop) => f_stop,
+            None if aperture > 0.0 => focal_length / aperture,
+            None => Exposure::default().f_stop,
+        };
+        let exposure = Exposure {
+            iso: args.iso.unwrap_or(Exposure::default().iso),
+            shutter_speed: args.shutter_speed.unwrap_or(default_speed),
+            f_stop,
+        };
+        let lens = match args.f_stop {
+            Some(_) => Lens {
+                aperture: exposure.aperture(view.vfov, args.units_per_meter),
+                ..lens.clone()
+            },
+            None => lens.clone(),
+        };
+        (lens, exposure.scale(&curve))
+    };
+
+    // Apply the command line's say over the view, for a shutter opening at
+    // time0
+    let adjust_view = |view: CameraView, scene: &Scene, time0: f64| {
+        let vfov = match (args.fov, projection) {
+            (Some(fov), _) => fov,
+            (None, Projection::Fisheye { .. }) => 180.0,
+            (None, _) => view.vfov,
+        };
+        let focus_dist = args.focus_dist.unwrap_or(view.focus_dist);
+        let view = CameraView { vfov, focus_dist, ..view };
+        match args.autofocus {
+            Some((s, t)) => view.autofocus(scene, vup, aspect_ratio, s, t, time0),
+            None => view,
         }
+    };
+
+    let make_camera = |view: &CameraView, lens: &Lens, time0: f64, time1: f64| {
+        let shutter = shutter(time0, time1);
+        match args.stereo {
+            Some(convergence) => StereoRig {
+                interocular: args.interocular.unwrap_or(view.focus_dist / 30.0),
+                convergence: match convergence {
+                    ConvergenceArg::Parallel => Convergence::Parallel,
+                    ConvergenceArg::ToeIn => Convergence::ToeIn,
+                },
+                layout: match args.stereo_layout {
+                    LayoutArg::SideBySide => StereoLayout::SideBySide,
+                    LayoutArg::TopBottom => StereoLayout::TopBottom,
+                },
+            }.camera(projection, view, vup, aspect_ratio, lens, &shutter),
+            None => projection.camera(view, vup, aspect_ratio, lens, &shutter),
+        }
+    };
+
+    if let Some((first, last)) = args.frames {
+        let fname = args.outfile.expect("--frames needs an output file");
+        for frame in first..=last {
+            let time0 = f64::from(frame) / args.fps;
+            let time1 = time0 + open_for(args.shutter / args.fps);
+            eprintln!("Frame {} (t = {:.3}s)", frame, time0);
+
+            let scene = (anim.build)(time0, time1);
+            let view = adjust_view(anim.camera.view(0.5 * (time0 + time1)), &scene, time0);
+            let (lens, exposure) = expose(&view);
+            let cam = make_camera(&view, &lens, time0, time1);
+            let film = render(&scene, cam.as_ref(), &RenderSettings { exposure, ..settings },
+                              passes);
+            finish(film, args.denoise, Some(frame_path(&fname, frame)),
+                   args.passes.as_ref().map(|p| frame_path(p, frame)));
+        }
+    } else {
+        let time1 = open_for(1.0);
+        let scene = (anim.build)(0.0, time1);
+        let view = adjust_view(anim.camera.view(0.0), &scene, 0.0);
+        let (lens, exposure) = expose(&view);
+        let cam = make_camera(&view, &lens, 0.0, time1);
+        let film = render(&scene, cam.as_ref(), &RenderSettings { exposure, ..settings },
+                          passes);
+        finish(film, args.denoise, args.outfile.clone(), args.passes.clone());
     }
-    write!(stderr, "\nDone\n");
 }
 
 /************ PDF Examples *************/
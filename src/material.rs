@@ -2,10 +2,12 @@ use crate::ray::Ray;
 use crate::hit::HitRecord;
 use crate::vec3::{Color, Vec3, Point3};
 use crate::vec3;
-use crate::util::random;
-use crate::texture::{Texture,FloatTexture,SolidColor,RandomBump};
+use crate::util::{random, hash_float};
+use crate::texture::{Texture,FloatTexture,SolidColor,RandomBump,ConstantFloat};
 use crate::util::PI;
-use crate::pdf::{PDensityFn,CosPDF,NullPDF,PhongPDF};
+use crate::pdf::{PDensityFn,CosPDF,NullPDF,PhongPDF,HenyeyGreensteinPDF,SheenPDF};
+use crate::medium::{MediumInterface,HomogeneousMedium};
+use crate::thin_film::ThinFilm;
 
 use std::sync::Arc;
 
@@ -13,6 +15,9 @@ pub struct ScatterRecord {
     pub specular_ray: Option<Ray>,
     pub attenuation: Color,
     pub specular_color: Option<Color>,
+    // In spectral mode, a further factor already at the ray's wavelengths,
+    // for color that only exists per wavelength (e.g. thin film interference)
+    pub spectral: Option<Color>,
     pub pdf: Arc<dyn PDensityFn + Sync + Send>,
 }
 
@@ -28,6 +33,46 @@ pub trait Material {
         Color(0.0, 0.0, 0.0)
     }
 
+    // attenuation * scattering_pdf, i.e. BRDF times cosine, for materials
+    // whose color can't be separated from the direction (e.g. measured data)
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        attenuation * self.scattering_pdf(ray_in, rec, scattered)
+    }
+
+    // Materials can declare what's inside of them, e.g. a scattering medium
+    // for subsurface scattering. Used when the geometry doesn't say otherwise.
+    fn interface(&self, _ray_in: &Ray, _rec: &HitRecord)
+                 -> Option<Arc<MediumInterface>> {
+        None
+    }
+
+    // Whether scattered directions depend on wavelength. When rendering
+    // spectrally, only the hero wavelength survives a dispersive scatter.
+    fn dispersive(&self, _ray_in: &Ray, _rec: &HitRecord) -> bool {
+        false
+    }
+
+    // Whether this is a phase function, i.e. scatters inside a medium rather
+    // than at a surface. Such hits have no meaningful normal.
+    fn volumetric(&self, _ray_in: &Ray, _rec: &HitRecord) -> bool {
+        false
+    }
+
+    // Whether light goes straight through the surface here as if it weren't
+    // there, e.g. a cutout's holes. Shadow rays carry on past such hits.
+    fn transparent(&self, _ray_in: &Ray, _rec: &HitRecord) -> bool {
+        false
+    }
+
+    // Whether scatter()'s attenuation holds whichever way the light goes.
+    // Materials whose PDF picks the color along with the direction (see
+    // PhongPDF) can only be sampled, not evaluated toward a given direction,
+    // so the integrators that join paths up can't connect through them.
+    fn deterministic(&self) -> bool {
+        true
+    }
+
     fn bump(&self, d: &Arc<dyn FloatTexture + Sync + Send>, rec: &HitRecord) -> HitRecord {
         let mut rec = (*rec).clone();
         let mut rec_eval = rec.clone();
@@ -89,6 +134,7 @@ impl Material for Lambertian {
         Some(ScatterRecord{
             specular_ray: None,
             specular_color: None,
+            spectral: None,
             attenuation: self.albedo.value(rec.u, rec.v, rec.p),
             pdf: CosPDF::new(rec.shading_geo.n).into(),
         })
@@ -104,9 +150,328 @@ impl Material for Lambertian {
     }
 }
 
+// Rough diffuse surfaces (clay, concrete, cloth), from Oren & Nayar,
+// "Generalization of Lambert's Reflectance Model". sigma is the standard
+// deviation of the microfacet slope angle, in radians. Zero is Lambertian.
+pub struct OrenNayar {
+    pub albedo: Arc<dyn Texture + Sync + Send>,
+    pub sigma: Arc<dyn FloatTexture + Sync + Send>,
+}
+
+impl OrenNayar {
+    #[must_use]
+    pub fn new(c: Color, sigma: f64) -> Self {
+        Self::from_texture(SolidColor::new(c).into(), ConstantFloat::new(sigma).into())
+    }
+
+    #[must_use]
+    pub fn from_texture(albedo: Arc<dyn Texture + Sync + Send>,
+                        sigma: Arc<dyn FloatTexture + Sync + Send>) -> Self {
+        Self { albedo, sigma }
+    }
+}
+
+impl From<OrenNayar> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: OrenNayar) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord{
+            specular_ray: None,
+            specular_color: None,
+            spectral: None,
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            pdf: CosPDF::new(rec.shading_geo.n).into(),
+        })
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let n = rec.shading_geo.n;
+        let wi = -ray_in.dir.unit_vector();
+        let wo = scattered.dir.unit_vector();
+        let cos_i = vec3::dot(n, wi).clamp(0.0, 1.0);
+        let cos_o = vec3::dot(n, wo);
+        if cos_o <= 0.0 {
+            return 0.0;
+        }
+        let cos_o = cos_o.min(1.0);
+
+        let sigma = self.sigma.value(rec.u, rec.v, rec.p);
+        let s2 = sigma * sigma;
+        let a = 1.0 - s2 / (2.0 * (s2 + 0.33));
+        let b = 0.45 * s2 / (s2 + 0.09);
+
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        let sin_o = (1.0 - cos_o * cos_o).sqrt();
+
+        // cos of the azimuthal angle between wi and wo
+        let cos_phi = if sin_i > 1e-4 && sin_o > 1e-4 {
+            let ti = (wi - cos_i * n).unit_vector();
+            let to = (wo - cos_o * n).unit_vector();
+            vec3::dot(ti, to).max(0.0)
+        } else {
+            0.0
+        };
+
+        // sin(alpha) * tan(beta), where alpha/beta are the larger/smaller
+        // of the two polar angles
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o)
+        };
+
+        cos_o / PI * (a + b * cos_phi * sin_alpha * tan_beta)
+    }
+}
+
+// Burley's diffuse from the Disney BRDF, which brightens toward grazing angles
+// as roughness goes up, i.e. gets retro-reflective.
+pub struct Burley {
+    pub albedo: Arc<dyn Texture + Sync + Send>,
+    pub roughness: Arc<dyn FloatTexture + Sync + Send>,
+}
+
+impl Burley {
+    #[must_use]
+    pub fn new(c: Color, roughness: f64) -> Self {
+        Self::from_texture(SolidColor::new(c).into(), ConstantFloat::new(roughness).into())
+    }
+
+    #[must_use]
+    pub fn from_texture(albedo: Arc<dyn Texture + Sync + Send>,
+                        roughness: Arc<dyn FloatTexture + Sync + Send>) -> Self {
+        Self { albedo, roughness }
+    }
+}
+
+impl From<Burley> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Burley) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Burley {
+    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord{
+            specular_ray: None,
+            specular_color: None,
+            spectral: None,
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            pdf: CosPDF::new(rec.shading_geo.n).into(),
+        })
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let n = rec.shading_geo.n;
+        let wi = -ray_in.dir.unit_vector();
+        let wo = scattered.dir.unit_vector();
+        let cos_i = vec3::dot(n, wi).clamp(0.0, 1.0);
+        let cos_o = vec3::dot(n, wo);
+        if cos_o <= 0.0 {
+            return 0.0;
+        }
+        let cos_o = cos_o.min(1.0);
+
+        let h = (wi + wo).unit_vector();
+        let cos_d = vec3::dot(h, wo).clamp(0.0, 1.0);
+        let roughness = self.roughness.value(rec.u, rec.v, rec.p);
+        let fd90 = 0.5 + 2.0 * roughness * cos_d * cos_d;
+        let schlick = |c: f64| 1.0 + (fd90 - 1.0) * (1.0 - c).powi(5);
+
+        cos_o / PI * schlick(cos_i) * schlick(cos_o)
+    }
+}
+
+// Charlie sheen (Estevez & Kulla 2017) for velvet and other fabrics, where
+// fibers sticking out of the surface catch light at grazing angles. On its
+// own this is just the fuzz; use SheenLayer to put it over a base material.
+pub struct Sheen {
+    pub color: Arc<dyn Texture + Sync + Send>,
+    pub roughness: f64,
+}
+
+impl Sheen {
+    #[must_use]
+    pub fn new(c: Color, roughness: f64) -> Self {
+        Self::from_texture(SolidColor::new(c).into(), roughness)
+    }
+
+    #[must_use]
+    pub fn from_texture(color: Arc<dyn Texture + Sync + Send>, roughness: f64) -> Self {
+        Self {
+            color,
+            roughness: SheenPDF::clamp_roughness(roughness),
+        }
+    }
+}
+
+impl From<Sheen> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Sheen) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord{
+            specular_ray: None,
+            specular_color: None,
+            spectral: None,
+            attenuation: self.color.value(rec.u, rec.v, rec.p),
+            pdf: SheenPDF::new(ray_in.dir, rec.shading_geo.n, self.roughness).into(),
+        })
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let n = rec.shading_geo.n;
+        let wi = -ray_in.dir.unit_vector();
+        let wo = scattered.dir.unit_vector();
+        let cos_i = vec3::dot(n, wi).clamp(0.0, 1.0);
+        let cos_o = vec3::dot(n, wo);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return 0.0;
+        }
+        let cos_h = vec3::dot((wi + wo).unit_vector(), n);
+        Self::value(cos_i, cos_o.min(1.0), cos_h, self.roughness)
+    }
+}
+
+impl Sheen {
+    // BRDF times cos, uncolored
+    fn value(cos_i: f64, cos_o: f64, cos_h: f64, roughness: f64) -> f64 {
+        // Neubelt & Pettineo's visibility term
+        let vis = 1.0 / (4.0 * (cos_i + cos_o - cos_i * cos_o));
+        SheenPDF::charlie_d(cos_h, roughness) * vis * cos_o
+    }
+
+    // Fraction of the light arriving at cos_i to the normal that the
+    // (uncolored) lobe reflects. Integrated numerically over the outgoing
+    // hemisphere; the lobe is symmetric about the plane of incidence.
+    fn albedo(cos_i: f64, roughness: f64) -> f64 {
+        const STEPS : usize = 64;
+        let cos_i = cos_i.clamp(1e-3, 1.0);
+        let wi = Vec3((1.0 - cos_i * cos_i).sqrt(), 0.0, cos_i);
+        let mut sum = 0.0;
+        for i in 0..STEPS {
+            let cos_o = (i as f64 + 0.5) / STEPS as f64;
+            let sin_o = (1.0 - cos_o * cos_o).sqrt();
+            for j in 0..STEPS {
+                let phi = PI * (j as f64 + 0.5) / STEPS as f64;
+                let wo = Vec3(sin_o * phi.cos(), sin_o * phi.sin(), cos_o);
+                let cos_h = (wi + wo).unit_vector().z();
+                sum += Self::value(cos_i, cos_o, cos_h, roughness);
+            }
+        }
+        // d(omega) = d(cos_o) d(phi), over both halves of the hemisphere
+        sum * 2.0 * PI / (STEPS * STEPS) as f64
+    }
+}
+
+// Sheen on top of some other material. Each hit picks one of the two lobes
+// (hashed, as in Mix) and weights it by the inverse of the probability of
+// having picked it, so the result is the sum of the two. What the sheen
+// reflects doesn't reach the base, so the base is scaled down by the sheen's
+// albedo in the incoming direction.
+pub struct SheenLayer {
+    base: Arc<dyn Material + Sync + Send>,
+    sheen: Sheen,
+    // the sheen lobe's albedo (uncolored), by cos to the normal from 0 to 1
+    albedo: Vec<f64>,
+}
+
+impl SheenLayer {
+    // Probability of picking the sheen lobe
+    const SHEEN_P : f64 = 0.5;
+
+    const ALBEDO_SIZE : usize = 32;
+
+    #[must_use]
+    pub fn new(base: Arc<dyn Material + Sync + Send>, sheen: Sheen) -> Self {
+        let albedo = (0..Self::ALBEDO_SIZE).map(|i| {
+            let cos_i = i as f64 / (Self::ALBEDO_SIZE - 1) as f64;
+            Sheen::albedo(cos_i, sheen.roughness)
+        }).collect();
+        Self { base, sheen, albedo }
+    }
+
+    fn picks_sheen(ray_in: &Ray, rec: &HitRecord) -> bool {
+        hash_float(rec.p, ray_in.dir) < Self::SHEEN_P
+    }
+
+    // What's left for the base once the sheen has had its share
+    fn base_weight(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        let cos_i = vec3::dot(-ray_in.dir.unit_vector(), rec.shading_geo.n).clamp(0.0, 1.0);
+        let x = cos_i * (Self::ALBEDO_SIZE - 1) as f64;
+        let i = (x as usize).min(Self::ALBEDO_SIZE - 2);
+        let t = x - i as f64;
+        let albedo = (1.0 - t) * self.albedo[i] + t * self.albedo[i + 1];
+        let sheen = self.sheen.color.value(rec.u, rec.v, rec.p) * albedo;
+        Color(
+            (1.0 - sheen.r()).max(0.0),
+            (1.0 - sheen.g()).max(0.0),
+            (1.0 - sheen.b()).max(0.0),
+        )
+    }
+}
+
+impl From<SheenLayer> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: SheenLayer) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for SheenLayer {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if Self::picks_sheen(ray_in, rec) {
+            return self.sheen.scatter(ray_in, rec);
+        }
+        let mut sr = self.base.scatter(ray_in, rec)?;
+        // scattering_value takes care of the weight otherwise
+        if sr.specular_ray.is_some() {
+            sr.attenuation = sr.attenuation * self.base_weight(ray_in, rec)
+                / (1.0 - Self::SHEEN_P);
+        }
+        Some(sr)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        if Self::picks_sheen(ray_in, rec) {
+            self.sheen.scattering_pdf(ray_in, rec, scattered) / Self::SHEEN_P
+        } else {
+            // no color to go on here, so take the base's share on average
+            let w = self.base_weight(ray_in, rec);
+            self.base.scattering_pdf(ray_in, rec, scattered) * (w.r() + w.g() + w.b()) / 3.0
+                / (1.0 - Self::SHEEN_P)
+        }
+    }
+
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        if Self::picks_sheen(ray_in, rec) {
+            self.sheen.scattering_value(ray_in, rec, attenuation, scattered) / Self::SHEEN_P
+        } else {
+            self.base.scattering_value(ray_in, rec, attenuation, scattered)
+                * self.base_weight(ray_in, rec) / (1.0 - Self::SHEEN_P)
+        }
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.base.emitted(ray_in, rec, u, v, p)
+    }
+
+    fn deterministic(&self) -> bool {
+        self.base.deterministic()
+    }
+}
+
 pub struct Metal {
     pub albedo: Arc<dyn Texture + Sync + Send>,
     pub fuzz: f64,
+    pub film: Option<ThinFilm>,
 }
 
 impl Metal {
@@ -115,8 +480,15 @@ impl Metal {
         Self {
             albedo: SolidColor::new(c).into(),
             fuzz,
+            film: None,
         }
     }
+
+    #[must_use]
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
 }
 
 impl From<Metal> for Arc<dyn Material + Sync + Send> {
@@ -139,22 +511,89 @@ impl Material for Metal {
             return None;
         }
 
+        let albedo = self.albedo.value(rec.u, rec.v, rec.p);
+        // the film's reflectance takes the place of the albedo; in spectral
+        // mode it's already at the ray's wavelengths
+        let (attenuation, spectral) = if let Some(film) = &self.film {
+            let cos_i = vec3::dot(-ray_in.dir.unit_vector(), rec.shading_geo.n);
+            let wavelengths = ray_in.wavelengths.as_ref();
+            let f = film.conductor_reflectance(
+                rec, cos_i.clamp(0.0, 1.0), rec.eta_ext, albedo, wavelengths,
+            );
+            if wavelengths.is_some() {
+                (Color(1.0, 1.0, 1.0), Some(f))
+            } else {
+                (f, None)
+            }
+        } else {
+            (albedo, None)
+        };
+
         Some(ScatterRecord{
             specular_ray: Some(Ray::new(
                 rec.p, dir, ray_in.time)
             ),
             specular_color: None,
-            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            spectral,
+            attenuation,
             pdf: NullPDF::new().into(),
         })
     }
 }
 
+// Wavelength-dependent index of refraction. Coefficients are in terms of
+// wavelength in micrometers, which is how they're usually tabulated.
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i * lambda^2 / (lambda^2 - c_i))
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Sodium d-line, where a material's nominal IOR is usually quoted
+    pub const LAMBDA_D : f64 = 587.6;
+
+    // Borosilicate crown glass (Schott N-BK7)
+    pub const BK7 : Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+
+    // Dense flint glass (Schott SF11)
+    pub const SF11 : Dispersion = Dispersion::Sellmeier {
+        b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+        c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+    };
+
+    pub const DIAMOND : Dispersion = Dispersion::Sellmeier {
+        b: [0.3306, 4.3356, 0.0],
+        c: [0.030_625, 0.011_236, 0.0],
+    };
+
+    #[must_use]
+    pub fn ior(&self, lambda_nm: f64) -> f64 {
+        let l2 = (lambda_nm / 1000.0) * (lambda_nm / 1000.0);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let n2 = 1.0 + b.iter().zip(c.iter())
+                    .map(|(bi, ci)| bi * l2 / (l2 - ci))
+                    .sum::<f64>();
+                n2.sqrt()
+            },
+        }
+    }
+}
+
 pub struct Dielectric {
     pub ir : f64,
     pub density: f64,
     pub vol_color: Color,
     pub albedo: Arc<dyn Texture + Sync + Send>,
+    pub dispersion: Option<Dispersion>,
+    pub film: Option<ThinFilm>,
 }
 
 impl Dielectric {
@@ -163,7 +602,24 @@ impl Dielectric {
         Self {
             ir, density,vol_color,
             albedo: SolidColor::new(Color(1.0, 1.0, 1.0)).into(),
+            dispersion: None,
+            film: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
 
+    // IOR varies with wavelength. Outside of spectral mode, this behaves like
+    // a plain dielectric with the IOR at the d-line.
+    #[must_use]
+    pub fn dispersive(dispersion: Dispersion, density: f64, vol_color: Color) -> Self {
+        Self {
+            dispersion: Some(dispersion),
+            ..Self::new(dispersion.ior(Dispersion::LAMBDA_D), density, vol_color)
         }
     }
 
@@ -188,16 +644,27 @@ impl From<Dielectric> for Arc<dyn Material + Sync + Send> {
 impl Material for Dielectric {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord)
                -> Option<ScatterRecord> {
+        let ir = match (self.dispersion, ray_in.wavelengths) {
+            (Some(d), Some(w)) => d.ior(w.hero()),
+            _ => self.ir,
+        };
+
+        // eta_ext is the IOR on the outside of the surface, which is only
+        // something other than air when dielectrics are nested
         let refraction_ratio : f64 = if rec.front_face {
-            1.0 / self.ir
+            rec.eta_ext / ir
         } else {
-            self.ir
+            ir / rec.eta_ext
         };
 
         let attenuation = if rec.front_face {
             self.albedo.value(rec.u, rec.v, rec.p)
         } else {
-            let absorb = Self::absorbance(rec.t, self.vol_color, self.density);
+            // Distance from the ray origin, which is where we refracted in
+            // if we got here by way of this surface. For anything fancier,
+            // give the surface an interior medium instead.
+            let dist = rec.t * ray_in.dir.len();
+            let absorb = Self::absorbance(dist, self.vol_color, self.density);
             let atten = absorb.exp();
             self.albedo.value(rec.u, rec.v, rec.p) * atten
         };
@@ -207,9 +674,34 @@ impl Material for Dielectric {
         let cos_theta = vec3::dot(-unit_direction, rec.shading_geo.n).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract: bool =
-            (refraction_ratio * sin_theta > 1.0) ||
-            Dielectric::reflectance(cos_theta, refraction_ratio) > random::double();
+        let (cannot_refract, attenuation, spectral) = if refraction_ratio * sin_theta > 1.0 {
+            (true, attenuation, None)
+        } else if let Some(film) = &self.film {
+            // The film makes reflectance colored, so pick reflection vs
+            // refraction by its average and reweight each channel (or each
+            // wavelength, in spectral mode)
+            let (eta_i, eta_t) = if rec.front_face {
+                (rec.eta_ext, ir)
+            } else {
+                (ir, rec.eta_ext)
+            };
+            let wavelengths = ray_in.wavelengths.as_ref();
+            let f = film.dielectric_reflectance(rec, cos_theta, eta_i, eta_t, wavelengths);
+            let p = ((f.r() + f.g() + f.b()) / 3.0).clamp(0.001, 0.999);
+            let (reflect, weight) = if random::double() < p {
+                (true, f / p)
+            } else {
+                (false, (Color(1.0, 1.0, 1.0) - f) / (1.0 - p))
+            };
+            if wavelengths.is_some() {
+                (reflect, attenuation, Some(weight))
+            } else {
+                (reflect, attenuation * weight, None)
+            }
+        } else {
+            (Dielectric::reflectance(cos_theta, refraction_ratio) > random::double(),
+             attenuation, None)
+        };
 
         let direction = if cannot_refract {
             vec3::reflect(unit_direction, rec.shading_geo.n)
@@ -224,10 +716,346 @@ impl Material for Dielectric {
                 ray_in.time
             )),
             specular_color: None,
+            spectral,
             attenuation,
             pdf: NullPDF::new().into(),
         })
     }
+
+    fn dispersive(&self, _ray_in: &Ray, _rec: &HitRecord) -> bool {
+        self.dispersion.is_some()
+    }
+}
+
+// Random-walk subsurface scattering. The surface itself is a smooth
+// dielectric, and the interior is a homogeneous medium defined by its
+// single-scattering albedo and mean free path (per color channel), which the
+// integrator walks through like any other interior medium.
+pub struct Subsurface {
+    boundary: Dielectric,
+    interface: Arc<MediumInterface>,
+}
+
+impl Subsurface {
+    #[must_use]
+    pub fn new(ir: f64, albedo: Color, mfp: Color, g: f64) -> Self {
+        Self::with_priority(ir, albedo, mfp, g, 0)
+    }
+
+    #[must_use]
+    pub fn with_priority(ir: f64, albedo: Color, mfp: Color, g: f64, priority: u32)
+                         -> Self {
+        let sigma_t = Color(1.0 / mfp.r(), 1.0 / mfp.g(), 1.0 / mfp.b());
+        let sigma_s = albedo * sigma_t;
+        let sigma_a = sigma_t - sigma_s;
+        Self {
+            boundary: Dielectric::new(ir, 0.0, Color(1.0, 1.0, 1.0)),
+            interface: Arc::new(MediumInterface::new(
+                Some(HomogeneousMedium::new(sigma_a, sigma_s, g).into()),
+                ir, priority,
+            )),
+        }
+    }
+}
+
+impl From<Subsurface> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Subsurface) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.boundary.scatter(ray_in, rec)
+    }
+
+    fn interface(&self, _ray_in: &Ray, _rec: &HitRecord)
+                 -> Option<Arc<MediumInterface>> {
+        Some(self.interface.clone())
+    }
+}
+
+// A smooth or rough dielectric coat over an arbitrary base material, e.g. car
+// paint or varnished wood. Each scatter either reflects off the coat (with
+// probability given by Fresnel) or passes through it to the base, in which
+// case we pay for transmission back out and for absorption in the coat on the
+// way in and out. Light bouncing around between the layers is ignored.
+pub struct Layered {
+    base: Arc<dyn Material + Sync + Send>,
+    ir: f64,
+    roughness: f64,
+    coat_color: Color,
+    thickness: f64,
+}
+
+impl Layered {
+    #[must_use]
+    pub fn new(base: Arc<dyn Material + Sync + Send>, ir: f64, roughness: f64) -> Self {
+        Self {
+            base, ir,
+            roughness: roughness.clamp(0.0, 1.0),
+            coat_color: Color(1.0, 1.0, 1.0),
+            thickness: 0.0,
+        }
+    }
+
+    // Tint the coat, with color being what's left after travelling through
+    // `thickness` of it at normal incidence
+    #[must_use]
+    pub fn with_absorption(mut self, coat_color: Color, thickness: f64) -> Self {
+        self.coat_color = coat_color;
+        self.thickness = thickness;
+        self
+    }
+
+    fn fresnel(&self, rec: &HitRecord, dir: Vec3) -> f64 {
+        let cos = vec3::dot(dir.unit_vector(), rec.shading_geo.n).abs().min(1.0);
+        Dielectric::reflectance(cos, rec.eta_ext / self.ir)
+    }
+
+    // Transmission back out of the coat toward scattered, relative to what
+    // scatter already accounted for
+    fn exit_weight(&self, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let avg = |c: Color| (c.r() + c.g() + c.b()) / 3.0;
+        let exit = avg(self.transmittance(rec, scattered.dir))
+            / avg(self.transmittance(rec, rec.shading_geo.n)).max(1e-8);
+        (1.0 - self.fresnel(rec, scattered.dir)) * exit
+    }
+
+    // Transmittance through the coat along dir, for one crossing
+    fn transmittance(&self, rec: &HitRecord, dir: Vec3) -> Color {
+        if self.thickness <= 0.0 {
+            return Color(1.0, 1.0, 1.0);
+        }
+        let cos = vec3::dot(dir.unit_vector(), rec.shading_geo.n).abs().max(0.01);
+        Dielectric::absorbance(self.thickness / cos, self.coat_color, 1.0).exp()
+    }
+}
+
+impl From<Layered> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Layered) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Layered {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if random::double() < self.fresnel(rec, ray_in.dir) {
+            let reflected = vec3::reflect(ray_in.dir.unit_vector(), rec.shading_geo.n);
+            let dir = reflected + self.roughness * Vec3::random_in_unit_sphere();
+            if vec3::dot(dir, rec.shading_geo.n) <= 0.0 {
+                return None;
+            }
+            return Some(ScatterRecord {
+                specular_ray: Some(Ray::new(rec.p, dir, ray_in.time)),
+                specular_color: None,
+                spectral: None,
+                attenuation: Color(1.0, 1.0, 1.0),
+                pdf: NullPDF::new().into(),
+            });
+        }
+
+        // The base sees the incoming direction as is, i.e. we don't bend it
+        // on the way through the coat
+        let mut sr = self.base.scatter(ray_in, rec)?;
+        sr.attenuation = sr.attenuation * self.transmittance(rec, ray_in.dir);
+        if let Some(spec_r) = &sr.specular_ray {
+            // we know the way out already, so account for it here
+            sr.attenuation = sr.attenuation
+                * self.transmittance(rec, spec_r.dir)
+                * (1.0 - self.fresnel(rec, spec_r.dir));
+        } else {
+            // We don't know the way out yet, so tint as if leaving straight
+            // up. scattering_pdf corrects for the actual exit angle.
+            sr.attenuation = sr.attenuation * self.transmittance(rec, rec.shading_geo.n);
+        }
+        Some(sr)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.base.scattering_pdf(ray_in, rec, scattered) * self.exit_weight(rec, scattered)
+    }
+
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        self.base.scattering_value(ray_in, rec, attenuation, scattered)
+            * self.exit_weight(rec, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.base.emitted(ray_in, rec, u, v, p)
+    }
+
+    // A glass or subsurface base is still filled with whatever it was, coat
+    // or no coat
+    fn interface(&self, ray_in: &Ray, rec: &HitRecord)
+                 -> Option<Arc<MediumInterface>> {
+        self.base.interface(ray_in, rec)
+    }
+
+    fn dispersive(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        self.base.dispersive(ray_in, rec)
+    }
+
+    fn deterministic(&self) -> bool {
+        self.base.deterministic()
+    }
+}
+
+// Blend of two materials. At each hit we use b with probability given by
+// `weight`, and a otherwise. The choice is hashed from the hit so that the
+// same material answers scatter, scattering_pdf and emitted.
+pub struct Mix {
+    a: Arc<dyn Material + Sync + Send>,
+    b: Arc<dyn Material + Sync + Send>,
+    weight: Arc<dyn FloatTexture + Sync + Send>,
+}
+
+impl Mix {
+    #[must_use]
+    pub fn new(a: Arc<dyn Material + Sync + Send>, b: Arc<dyn Material + Sync + Send>,
+               weight: Arc<dyn FloatTexture + Sync + Send>) -> Self {
+        Self { a, b, weight }
+    }
+
+    fn select(&self, ray_in: &Ray, rec: &HitRecord) -> &Arc<dyn Material + Sync + Send> {
+        let w = self.weight.value(rec.u, rec.v, rec.p);
+        if hash_float(rec.p, ray_in.dir) < w {
+            &self.b
+        } else {
+            &self.a
+        }
+    }
+}
+
+impl From<Mix> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Mix) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Mix {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.select(ray_in, rec).scatter(ray_in, rec)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.select(ray_in, rec).scattering_pdf(ray_in, rec, scattered)
+    }
+
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        self.select(ray_in, rec).scattering_value(ray_in, rec, attenuation, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        self.select(ray_in, rec).emitted(ray_in, rec, u, v, p)
+    }
+
+    fn interface(&self, ray_in: &Ray, rec: &HitRecord)
+                 -> Option<Arc<MediumInterface>> {
+        self.select(ray_in, rec).interface(ray_in, rec)
+    }
+
+    fn dispersive(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        self.select(ray_in, rec).dispersive(ray_in, rec)
+    }
+
+    fn volumetric(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        self.select(ray_in, rec).volumetric(ray_in, rec)
+    }
+
+    fn transparent(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        self.select(ray_in, rec).transparent(ray_in, rec)
+    }
+
+    fn deterministic(&self) -> bool {
+        self.a.deterministic() && self.b.deterministic()
+    }
+}
+
+// Cut holes in a surface wherever `opacity` is below `threshold`, e.g. for
+// leaves on alpha-mapped cards. Rays carry on straight through the holes.
+pub struct AlphaCutout {
+    mat: Arc<dyn Material + Sync + Send>,
+    opacity: Arc<dyn FloatTexture + Sync + Send>,
+    threshold: f64,
+}
+
+impl AlphaCutout {
+    #[must_use]
+    pub fn new(mat: Arc<dyn Material + Sync + Send>,
+               opacity: Arc<dyn FloatTexture + Sync + Send>, threshold: f64) -> Self {
+        Self { mat, opacity, threshold }
+    }
+
+    fn is_hole(&self, rec: &HitRecord) -> bool {
+        self.opacity.value(rec.u, rec.v, rec.p) < self.threshold
+    }
+}
+
+impl From<AlphaCutout> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: AlphaCutout) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for AlphaCutout {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        if self.is_hole(rec) {
+            return Some(ScatterRecord {
+                specular_ray: Some(Ray::new(rec.p, ray_in.dir, ray_in.time)),
+                specular_color: None,
+                spectral: None,
+                attenuation: Color(1.0, 1.0, 1.0),
+                pdf: NullPDF::new().into(),
+            });
+        }
+        self.mat.scatter(ray_in, rec)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        self.mat.scattering_pdf(ray_in, rec, scattered)
+    }
+
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        self.mat.scattering_value(ray_in, rec, attenuation, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord, u: f64, v: f64, p: Point3) -> Color {
+        if self.is_hole(rec) {
+            Color(0.0, 0.0, 0.0)
+        } else {
+            self.mat.emitted(ray_in, rec, u, v, p)
+        }
+    }
+
+    // A hole isn't there at all, so it has nothing inside it
+    fn interface(&self, ray_in: &Ray, rec: &HitRecord)
+                 -> Option<Arc<MediumInterface>> {
+        if self.is_hole(rec) {
+            None
+        } else {
+            self.mat.interface(ray_in, rec)
+        }
+    }
+
+    fn dispersive(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        !self.is_hole(rec) && self.mat.dispersive(ray_in, rec)
+    }
+
+    fn volumetric(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        !self.is_hole(rec) && self.mat.volumetric(ray_in, rec)
+    }
+
+    fn transparent(&self, ray_in: &Ray, rec: &HitRecord) -> bool {
+        self.is_hole(rec) || self.mat.transparent(ray_in, rec)
+    }
+
+    fn deterministic(&self) -> bool {
+        self.mat.deterministic()
+    }
 }
 
 pub struct DiffuseLight {
@@ -290,10 +1118,80 @@ impl Material for Isotropic {
                 rec.p, Vec3::random_in_unit_sphere(), ray_in.time,
             )),
             specular_color: None,
+            spectral: None,
             attenuation: self.albedo.value(rec.u, rec.v, rec.p),
             pdf: NullPDF::new().into(),
         })
     }
+
+    fn volumetric(&self, _ray_in: &Ray, _rec: &HitRecord) -> bool {
+        true
+    }
+}
+
+// Anisotropic phase function for participating media. Unlike Isotropic, the
+// scattered direction comes from a PDF, so volumes get light sampling too.
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture + Sync + Send>,
+    pub g1: f64,
+    pub g2: f64,
+    pub w: f64,
+}
+
+impl HenyeyGreenstein {
+    #[must_use]
+    pub fn new(c: Color, g: f64) -> Self {
+        Self::from_texture(SolidColor::new(c).into(), g)
+    }
+
+    #[must_use]
+    pub fn from_texture(albedo: Arc<dyn Texture + Sync + Send>, g: f64)
+                        -> Self {
+        Self {
+            albedo, g1: g, g2: 0.0, w: 1.0,
+        }
+    }
+
+    // Two lobes blended as w * HG(g1) + (1 - w) * HG(g2)
+    #[must_use]
+    pub fn double(c: Color, g1: f64, g2: f64, w: f64) -> Self {
+        Self {
+            albedo: SolidColor::new(c).into(),
+            g1, g2,
+            w: w.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl From<HenyeyGreenstein> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: HenyeyGreenstein) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord{
+            specular_ray: None,
+            specular_color: None,
+            spectral: None,
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            pdf: HenyeyGreensteinPDF::double(
+                ray_in.dir, self.g1, self.g2, self.w,
+            ).into(),
+        })
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = vec3::dot(
+            ray_in.dir.unit_vector(), scattered.dir.unit_vector()
+        );
+        HenyeyGreensteinPDF::double_phase(cos_theta, self.g1, self.g2, self.w)
+    }
+
+    fn volumetric(&self, _ray_in: &Ray, _rec: &HitRecord) -> bool {
+        true
+    }
 }
 
 pub struct WfMtl {
@@ -362,6 +1260,10 @@ impl Material for WfMtl {
         }
         // self.diffuse.scattering_pdf(ray_in, rec, scattered)
     }
+
+    fn deterministic(&self) -> bool {
+        self.model <= 1
+    }
 }
 
 pub struct Corroded {
@@ -399,6 +1301,15 @@ impl Material for Corroded {
             // self.mat.scattering_pdf(ray_in, rec, scattered)
     }
 
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        let rec = self.bump(&self.bump_t, rec);
+        self.mat.scattering_value(ray_in, &rec, attenuation, scattered)
+    }
+
+    fn deterministic(&self) -> bool {
+        self.mat.deterministic()
+    }
 }
 
 pub struct AnisotropicPhong {
@@ -433,6 +1344,7 @@ impl Material for AnisotropicPhong {
             specular_ray: None,
             attenuation: self.albedo.value(rec.u, rec.v, rec.p),
             specular_color: Some(self.specular.value(rec.u, rec.v, rec.p)),
+            spectral: None,
             pdf: pdf.clone()
         };
         let dir = pdf.generate(&mut sr);
@@ -451,4 +1363,8 @@ impl Material for AnisotropicPhong {
             cosine / PI
         }
     }
+
+    fn deterministic(&self) -> bool {
+        false
+    }
 }
@@ -0,0 +1,235 @@
+use crate::vec3::{Vec3,Color};
+use crate::ray::Ray;
+use crate::hit::{HitRecord,Hittable};
+use crate::material::{Material,HenyeyGreenstein};
+use crate::aabb::AABB;
+use crate::util::{random, INFINITY};
+
+use std::sync::Arc;
+
+// A participating medium that fills the inside of a surface, as opposed to
+// ConstantMedium & co, which are Hittables in their own right. These are
+// sampled by the integrator while a ray travels through the interior.
+pub trait Medium {
+    // Sample a free-flight distance along r, up to t_max (the next surface).
+    // Returns a scattering event inside the medium, if there is one before
+    // t_max, along with the throughput weight for whichever event was chosen.
+    fn sample(&self, r: &Ray, t_max: f64) -> (Option<HitRecord>, Color);
+
+    fn transmittance(&self, r: &Ray, t_max: f64) -> Color;
+}
+
+// Homogeneous medium with per-channel absorption and scattering coefficients,
+// so e.g. water can absorb red faster than blue.
+pub struct HomogeneousMedium {
+    sigma_a: Color,
+    sigma_s: Color,
+    phase_fn: Arc<dyn Material + Sync + Send>,
+}
+
+impl HomogeneousMedium {
+    #[must_use]
+    pub fn new(sigma_a: Color, sigma_s: Color, g: f64) -> Self {
+        Self {
+            sigma_a, sigma_s,
+            // sigma_s already carries the albedo, so the phase function doesn't
+            phase_fn: HenyeyGreenstein::new(Color(1.0, 1.0, 1.0), g).into(),
+        }
+    }
+
+    // Purely absorbing, e.g. tinted glass or clear liquids
+    #[must_use]
+    pub fn absorbing(sigma_a: Color) -> Self {
+        Self::new(sigma_a, Color::new(), 0.0)
+    }
+
+    // (sigma_a, sigma_s) as seen by r, i.e. at its wavelengths if it has any
+    fn coeffs(&self, r: &Ray) -> (Color, Color) {
+        match r.wavelengths {
+            Some(w) => (w.spectrum(self.sigma_a), w.spectrum(self.sigma_s)),
+            None => (self.sigma_a, self.sigma_s),
+        }
+    }
+
+    fn avg(c: Color) -> f64 {
+        (c.r() + c.g() + c.b()) / 3.0
+    }
+}
+
+impl From<HomogeneousMedium> for Arc<dyn Medium + Sync + Send> {
+    fn from(mm: HomogeneousMedium) -> Arc<dyn Medium + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Medium for HomogeneousMedium {
+    // Pick a color channel uniformly and sample distance by its extinction.
+    // The weight divides by the pdf averaged over all three channels, which
+    // keeps chromatic media unbiased (see pbrt's HomogeneousMedium).
+    fn sample(&self, r: &Ray, t_max: f64) -> (Option<HitRecord>, Color) {
+        if self.sigma_s.near_zero() {
+            return (None, self.transmittance(r, t_max));
+        }
+
+        let (sigma_a, sigma_s) = self.coeffs(r);
+        let sigma_t = sigma_a + sigma_s;
+        let ray_length = r.dir.len();
+        let st = sigma_t[random::uint(0, 2)];
+        let dist = if st > 0.0 {
+            -f64::ln(1.0 - random::double()) / st
+        } else {
+            INFINITY
+        };
+
+        let t = f64::min(dist / ray_length, t_max);
+        let sampled = t < t_max;
+
+        let tr = self.transmittance(r, t);
+        let density = if sampled { sigma_t * tr } else { tr };
+        let pdf = Self::avg(density);
+        if pdf <= 0.0 {
+            return (None, Color::new());
+        }
+
+        if sampled {
+            let hr = HitRecord::new(
+                r, r.at(t), Vec3(1.0, 0.0, 0.0), t, 0.0, 0.0, self.phase_fn.clone()
+            );
+            (Some(hr), tr * sigma_s / pdf)
+        } else {
+            (None, tr / pdf)
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_max: f64) -> Color {
+        let (sigma_a, sigma_s) = self.coeffs(r);
+        let dist = f64::min(t_max * r.dir.len(), f64::MAX);
+        (-dist * (sigma_a + sigma_s)).exp()
+    }
+}
+
+// What lies on the inside of a surface: an optional medium, the index of
+// refraction, and a priority for resolving overlapping (nested) volumes. If
+// two volumes overlap, the one with the higher priority wins, so e.g. the
+// walls of a glass should outrank the liquid poured into it.
+pub struct MediumInterface {
+    pub inside: Option<Arc<dyn Medium + Sync + Send>>,
+    pub ior: f64,
+    pub priority: u32,
+}
+
+impl MediumInterface {
+    #[must_use]
+    pub fn new(inside: Option<Arc<dyn Medium + Sync + Send>>, ior: f64, priority: u32)
+               -> Self {
+        Self {
+            inside, ior, priority,
+        }
+    }
+}
+
+// Attach a MediumInterface to every hit on obj. For dielectrics, the ior here
+// should match that of the surface material.
+pub struct MediumBoundary {
+    obj: Arc<dyn Hittable + Sync + Send>,
+    interface: Arc<MediumInterface>,
+}
+
+impl MediumBoundary {
+    #[must_use]
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, interface: MediumInterface)
+               -> Self {
+        Self {
+            obj,
+            interface: Arc::new(interface),
+        }
+    }
+}
+
+impl From<MediumBoundary> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: MediumBoundary) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for MediumBoundary {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut hr = self.obj.hit(r, t_min, t_max)?;
+        hr.interface = Some(self.interface.clone());
+        Some(hr)
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.obj.materials(f);
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.obj.bounding_box(time0, time1)
+    }
+
+    fn pdf_value(&self, origin: Vec3, v: Vec3) -> f64 {
+        self.obj.pdf_value(origin, v)
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.obj.random(origin)
+    }
+}
+
+// The interfaces a path is currently inside of, in the order they were
+// entered. This is what lets us find the medium the ray is travelling
+// through and the IOR on the far side of a surface.
+#[derive(Default, Clone)]
+pub struct MediumStack {
+    entries: Vec<Arc<MediumInterface>>,
+}
+
+impl MediumStack {
+    #[must_use]
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    // Highest priority entry; ties go to the most recently entered
+    fn top_excluding(&self, skip: Option<&Arc<MediumInterface>>)
+                     -> Option<&Arc<MediumInterface>> {
+        self.entries.iter()
+            .filter(|e| skip.is_none_or(|s| !Arc::ptr_eq(e, s)))
+            .fold(None, |best: Option<&Arc<MediumInterface>>, e| match best {
+                Some(b) if b.priority > e.priority => Some(b),
+                _ => Some(e),
+            })
+    }
+
+    #[must_use]
+    pub fn medium(&self) -> Option<Arc<dyn Medium + Sync + Send>> {
+        self.top_excluding(None).and_then(|e| e.inside.clone())
+    }
+
+    // A hit is "false" if we're inside something of higher priority, in
+    // which case the ray should pass through the surface untouched.
+    #[must_use]
+    pub fn is_true_hit(&self, iface: &Arc<MediumInterface>, entering: bool) -> bool {
+        let others = if entering {
+            self.top_excluding(None)
+        } else {
+            self.top_excluding(Some(iface))
+        };
+        others.is_none_or(|t| iface.priority >= t.priority)
+    }
+
+    // IOR of whatever is on the other side of iface from its interior
+    #[must_use]
+    pub fn outside_ior(&self, iface: &Arc<MediumInterface>) -> f64 {
+        self.top_excluding(Some(iface)).map_or(1.0, |t| t.ior)
+    }
+
+    // Record that the path passed through iface
+    pub fn cross(&mut self, iface: &Arc<MediumInterface>, entering: bool) {
+        if entering {
+            self.entries.push(iface.clone());
+        } else if let Some(i) = self.entries.iter().rposition(|e| Arc::ptr_eq(e, iface)) {
+            self.entries.remove(i);
+        }
+    }
+}
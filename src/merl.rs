@@ -0,0 +1,197 @@
+use crate::vec3::{Vec3,Color};
+use crate::vec3;
+use crate::ray::Ray;
+use crate::hit::HitRecord;
+use crate::material::{Material,ScatterRecord};
+use crate::onb::OrthoNormalBasis;
+use crate::pdf::GlossyPDF;
+use crate::util::PI;
+
+use std::sync::Arc;
+use std::path::Path;
+
+// Measured isotropic BRDF in the MERL 100 ".binary" format (Matusik et al.,
+// "A Data-Driven Reflectance Model"). Samples are tabulated over the
+// Rusinkiewicz half/difference angles: 90 theta_h x 90 theta_d x 180 phi_d,
+// with theta_h spaced non-uniformly to get more of them near the highlight.
+pub struct Merl {
+    data: Vec<f64>,
+    // Blinn lobe fit to the data, used for importance sampling
+    exponent: f64,
+    spec_weight: f64,
+}
+
+const RES_THETA_H : usize = 90;
+const RES_THETA_D : usize = 90;
+const RES_PHI_D : usize = 180;
+const N_SAMPLES : usize = RES_THETA_H * RES_THETA_D * RES_PHI_D;
+
+const RED_SCALE : f64 = 1.0 / 1500.0;
+const GREEN_SCALE : f64 = 1.15 / 1500.0;
+const BLUE_SCALE : f64 = 1.66 / 1500.0;
+
+impl Merl {
+    /// # Panics
+    ///
+    /// Panics if the file can't be read or isn't a MERL BRDF
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(fname: P) -> Self {
+        let bytes = std::fs::read(fname.as_ref()).expect("File not found!");
+        assert!(bytes.len() >= 12, "{}: truncated", fname.as_ref().display());
+
+        let dims: Vec<usize> = bytes[..12].chunks(4).map(|c| {
+            i32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize
+        }).collect();
+        assert!(dims.iter().product::<usize>() == N_SAMPLES,
+                "{}: unexpected dimensions {:?}", fname.as_ref().display(), dims);
+
+        let data: Vec<f64> = bytes[12..].chunks_exact(8).map(|c| {
+            f64::from_le_bytes([c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]])
+        }).collect();
+        assert!(data.len() == 3 * N_SAMPLES,
+                "{}: expected {} samples, got {}",
+                fname.as_ref().display(), 3 * N_SAMPLES, data.len());
+
+        let mut result = Self {
+            data,
+            exponent: 1.0,
+            spec_weight: 0.0,
+        };
+        result.fit_lobe();
+        eprintln!("{}: Blinn fit: exponent {:.1}, specular weight {:.2}",
+                  fname.as_ref().display(), result.exponent, result.spec_weight);
+        result
+    }
+
+    fn theta_h_index(theta_h: f64) -> usize {
+        if theta_h <= 0.0 {
+            return 0;
+        }
+        let idx = f64::sqrt(theta_h / (PI / 2.0)) * RES_THETA_H as f64;
+        (idx as usize).min(RES_THETA_H - 1)
+    }
+
+    fn theta_d_index(theta_d: f64) -> usize {
+        let idx = theta_d / (PI / 2.0) * RES_THETA_D as f64;
+        (f64::max(idx, 0.0) as usize).min(RES_THETA_D - 1)
+    }
+
+    // Reciprocity means phi_d and phi_d + pi are the same
+    fn phi_d_index(phi_d: f64) -> usize {
+        let phi_d = if phi_d < 0.0 { phi_d + PI } else { phi_d };
+        let idx = phi_d / PI * RES_PHI_D as f64;
+        (f64::max(idx, 0.0) as usize).min(RES_PHI_D - 1)
+    }
+
+    fn lookup_index(&self, i_h: usize, i_d: usize, i_phi: usize) -> Color {
+        let ind = i_phi + i_d * RES_PHI_D + i_h * RES_PHI_D * RES_THETA_D;
+        Color(
+            f64::max(0.0, self.data[ind] * RED_SCALE),
+            f64::max(0.0, self.data[ind + N_SAMPLES] * GREEN_SCALE),
+            f64::max(0.0, self.data[ind + 2 * N_SAMPLES] * BLUE_SCALE),
+        )
+    }
+
+    fn rotate(v: Vec3, axis: Vec3, angle: f64) -> Vec3 {
+        // Rodrigues' rotation formula
+        let (sin, cos) = angle.sin_cos();
+        v * cos + vec3::cross(axis, v) * sin + axis * vec3::dot(axis, v) * (1.0 - cos)
+    }
+
+    // BRDF for directions in the local frame (z up)
+    fn eval(&self, wi: Vec3, wo: Vec3) -> Color {
+        let h = (wi + wo).unit_vector();
+        let theta_h = h.z().clamp(-1.0, 1.0).acos();
+        let phi_h = f64::atan2(h.y(), h.x());
+
+        // wi, relative to the half vector
+        let d = Self::rotate(wi, Vec3(0.0, 0.0, 1.0), -phi_h);
+        let d = Self::rotate(d, Vec3(0.0, 1.0, 0.0), -theta_h);
+        let theta_d = d.z().clamp(-1.0, 1.0).acos();
+        let phi_d = f64::atan2(d.y(), d.x());
+
+        self.lookup_index(
+            Self::theta_h_index(theta_h),
+            Self::theta_d_index(theta_d),
+            Self::phi_d_index(phi_d),
+        )
+    }
+
+    // Fit a Blinn lobe to the highlight, looking along theta_h near the
+    // retro-reflective configuration. The floor of the curve is taken as the
+    // diffuse part, and the exponent comes from where the rest falls to half.
+    fn fit_lobe(&mut self) {
+        let lum = |c: Color| (c.r() + c.g() + c.b()) / 3.0;
+        let theta_h = |i: usize| {
+            let t = i as f64 / RES_THETA_H as f64;
+            t * t * PI / 2.0
+        };
+        let curve: Vec<f64> = (0..RES_THETA_H).map(|i| {
+            lum(self.lookup_index(i, RES_THETA_D / 9, RES_PHI_D / 2))
+        }).collect();
+
+        let diffuse = curve.iter().copied().fold(f64::INFINITY, f64::min);
+        let peak = curve[0] - diffuse;
+        if peak <= 0.0 {
+            return;
+        }
+
+        let half = (0..RES_THETA_H).find(|i| curve[*i] - diffuse < 0.5 * peak)
+            .unwrap_or(RES_THETA_H - 1);
+        let cos_half = theta_h(half.max(1)).cos();
+        self.exponent = (f64::ln(0.5) / f64::ln(cos_half)).clamp(1.0, 10000.0);
+
+        // albedos of a normalized Blinn lobe with this peak and of the floor
+        let spec_albedo = peak * 8.0 * PI / (self.exponent + 8.0);
+        let diffuse_albedo = diffuse * PI;
+        self.spec_weight =
+            (spec_albedo / (spec_albedo + diffuse_albedo)).clamp(0.1, 0.9);
+    }
+
+    fn local_frame(rec: &HitRecord) -> OrthoNormalBasis {
+        let mut uvw = OrthoNormalBasis::new();
+        uvw.build_from_w(rec.shading_geo.n);
+        uvw
+    }
+
+    fn to_local(uvw: &OrthoNormalBasis, v: Vec3) -> Vec3 {
+        Vec3(vec3::dot(v, uvw.u()), vec3::dot(v, uvw.v()), vec3::dot(v, uvw.w()))
+    }
+}
+
+impl From<Merl> for Arc<dyn Material + Sync + Send> {
+    fn from(mm: Merl) -> Arc<dyn Material + Sync + Send> {
+        Arc::new(mm)
+    }
+}
+
+impl Material for Merl {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            specular_ray: None,
+            specular_color: None,
+            spectral: None,
+            // all of the color is in scattering_value
+            attenuation: Color(1.0, 1.0, 1.0),
+            pdf: GlossyPDF::new(
+                ray_in.dir, rec.shading_geo.n, self.exponent, self.spec_weight
+            ).into(),
+        })
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let v = self.scattering_value(ray_in, rec, Color(1.0, 1.0, 1.0), scattered);
+        (v.r() + v.g() + v.b()) / 3.0
+    }
+
+    fn scattering_value(&self, ray_in: &Ray, rec: &HitRecord, attenuation: Color,
+                        scattered: &Ray) -> Color {
+        let uvw = Self::local_frame(rec);
+        let wi = Self::to_local(&uvw, -ray_in.dir.unit_vector());
+        let wo = Self::to_local(&uvw, scattered.dir.unit_vector());
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return Color(0.0, 0.0, 0.0);
+        }
+        attenuation * self.eval(wi, wo) * wo.z()
+    }
+}
@@ -83,6 +83,10 @@ impl Hittable for MovingSphere {
         }
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mat);
+    }
+
     // Take the box for the sphere's initial position and the box for the sphere's
     // final position, and compute a bounding box around those. The MovingSphere is
     // then guaranteed to reside in that BB at any given time.
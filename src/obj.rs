@@ -6,7 +6,7 @@ use crate::aabb::AABB;
 use crate::triangle::Triangle;
 use crate::hittable_list::HittableList;
 use crate::bvh::BVHNode;
-use crate::material::{Metal, Lambertian, WfMtl};
+use crate::material::{Metal, Lambertian, WfMtl, AlphaCutout};
 use crate::triangle_mesh::TriangleMesh;
 use crate::texture::Texture;
 use crate::texture;
@@ -140,9 +140,19 @@ impl WfObject {
 
         let model = if let Some(m) = mm.illumination_model { m } else { 1 };
 
-        WfMtl::new(
+        let mat: Arc<dyn Material + Sync + Send> = WfMtl::new(
             model, ns, diffuse, specular, ambient,
-        ).into()
+        ).into();
+
+        // map_d, i.e. an opacity mask (leaf cards & such)
+        if let Some(tx) = &mm.dissolve_texture {
+            let name = dir.unwrap_or(Path::new(".")).join(tx);
+            AlphaCutout::new(
+                mat, texture::ImageFloat::new(name.as_path()).into(), 0.5
+            ).into()
+        } else {
+            mat
+        }
     }
 }
 
@@ -158,6 +168,10 @@ impl Hittable for WfObject {
         self.triangles.hit(r, t_min, t_max)
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.triangles.materials(f);
+    }
+
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
         self.triangles.bounding_box(time0, time1)
     }
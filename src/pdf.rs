@@ -130,6 +130,224 @@ impl PDensityFn for MixturePDF {
     }
 }
 
+// Henyey-Greenstein phase function, optionally as a mixture of two lobes
+// (e.g. a strong forward lobe plus a weak back-scattering one). Angles are
+// measured from the direction the light was travelling in, so g > 0 is
+// forward scattering and g < 0 is back scattering.
+pub struct HenyeyGreensteinPDF {
+    uvw: OrthoNormalBasis,
+    g1: f64,
+    g2: f64,
+    w: f64,
+}
+
+impl HenyeyGreensteinPDF {
+    #[must_use]
+    pub fn new(incident: Vec3, g: f64) -> Self {
+        Self::double(incident, g, 0.0, 1.0)
+    }
+
+    // w is the weight of the g1 lobe; (1 - w) goes to g2
+    #[must_use]
+    pub fn double(incident: Vec3, g1: f64, g2: f64, w: f64) -> Self {
+        let mut uvw = OrthoNormalBasis::new();
+        uvw.build_from_w(incident);
+        Self {
+            uvw, g1, g2, w,
+        }
+    }
+
+    #[must_use]
+    pub fn phase(cos_theta: f64, g: f64) -> f64 {
+        let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denom * f64::sqrt(denom))
+    }
+
+    #[must_use]
+    pub fn double_phase(cos_theta: f64, g1: f64, g2: f64, w: f64) -> f64 {
+        w * Self::phase(cos_theta, g1) + (1.0 - w) * Self::phase(cos_theta, g2)
+    }
+
+    // Invert the HG CDF for cos(theta)
+    fn sample_cos_theta(g: f64) -> f64 {
+        let xi = random::double();
+        if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - sq * sq) / (2.0 * g)).clamp(-1.0, 1.0)
+        }
+    }
+}
+
+impl From<HenyeyGreensteinPDF> for Arc<dyn PDensityFn + Sync + Send> {
+    fn from(pdf: HenyeyGreensteinPDF) -> Arc<dyn PDensityFn + Sync + Send> {
+        Arc::new(pdf)
+    }
+}
+
+impl PDensityFn for HenyeyGreensteinPDF {
+    fn value(&self, dir: Vec3) -> f64 {
+        let cos_theta = dot(dir.unit_vector(), self.uvw.w());
+        Self::double_phase(cos_theta, self.g1, self.g2, self.w)
+    }
+
+    fn generate(&self, _sr: &mut ScatterRecord) -> Vec3 {
+        let g = if random::double() < self.w { self.g1 } else { self.g2 };
+        let cos_theta = Self::sample_cos_theta(g);
+        let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = 2.0 * PI * random::double();
+        self.uvw.local(
+            sin_theta * f64::cos(phi),
+            sin_theta * f64::sin(phi),
+            cos_theta,
+        )
+    }
+}
+
+// Sampling for the Charlie sheen lobe (see material::Sheen). Half the time we
+// sample half vectors from the sheen distribution itself, and the rest of the
+// time uniformly over the hemisphere, since the distribution piles up at
+// grazing half vectors that often reflect below the surface.
+pub struct SheenPDF {
+    uvw: OrthoNormalBasis,
+    wi: Vec3,
+    roughness: f64,
+}
+
+impl SheenPDF {
+    #[must_use]
+    pub fn new(incident: Vec3, norm: Vec3, roughness: f64) -> Self {
+        let mut uvw = OrthoNormalBasis::new();
+        uvw.build_from_w(norm);
+        Self {
+            uvw,
+            wi: -incident.unit_vector(),
+            roughness: Self::clamp_roughness(roughness),
+        }
+    }
+
+    #[must_use]
+    pub fn clamp_roughness(roughness: f64) -> f64 {
+        roughness.clamp(0.05, 1.0)
+    }
+
+    // Charlie distribution (Estevez & Kulla, "Production Friendly Microfacet
+    // Sheen BRDF"), for the cos of the angle between half vector & normal
+    #[must_use]
+    pub fn charlie_d(cos_h: f64, roughness: f64) -> f64 {
+        let inv_r = 1.0 / roughness;
+        let sin2_h = f64::max(0.0, 1.0 - cos_h * cos_h);
+        (2.0 + inv_r) * sin2_h.powf(0.5 * inv_r) / (2.0 * PI)
+    }
+
+    fn half_vector_value(&self, dir: Vec3) -> f64 {
+        let h = (self.wi + dir).unit_vector();
+        let cos_h = dot(h, self.uvw.w()).abs();
+        let cos_oh = dot(dir, h).abs();
+        if cos_oh <= 0.0 {
+            return 0.0;
+        }
+        Self::charlie_d(cos_h, self.roughness) * cos_h / (4.0 * cos_oh)
+    }
+}
+
+impl From<SheenPDF> for Arc<dyn PDensityFn + Sync + Send> {
+    fn from(pdf: SheenPDF) -> Arc<dyn PDensityFn + Sync + Send> {
+        Arc::new(pdf)
+    }
+}
+
+impl PDensityFn for SheenPDF {
+    fn value(&self, dir: Vec3) -> f64 {
+        let dir = dir.unit_vector();
+        let uniform = if dot(dir, self.uvw.w()) > 0.0 {
+            1.0 / (2.0 * PI)
+        } else {
+            0.0
+        };
+        0.5 * uniform + 0.5 * self.half_vector_value(dir)
+    }
+
+    fn generate(&self, _sr: &mut ScatterRecord) -> Vec3 {
+        let phi = 2.0 * PI * random::double();
+        if random::double() < 0.5 {
+            let cos_theta = random::double();
+            let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
+            return self.uvw.local(
+                sin_theta * f64::cos(phi),
+                sin_theta * f64::sin(phi),
+                cos_theta,
+            );
+        }
+        // The CDF of D(h) cos(h) in terms of sin(h) is sin^(2 + 1/r)
+        let sin_h = random::double().powf(1.0 / (2.0 + 1.0 / self.roughness));
+        let cos_h = f64::sqrt(f64::max(0.0, 1.0 - sin_h * sin_h));
+        let h = self.uvw.local(sin_h * f64::cos(phi), sin_h * f64::sin(phi), cos_h);
+        vec3::reflect(-self.wi, h)
+    }
+}
+
+// Cosine-weighted diffuse plus a Blinn lobe around the mirror direction, in
+// proportions given by spec_weight. Good for anything glossy we can only
+// approximate, e.g. measured BRDFs after fitting an exponent to them.
+pub struct GlossyPDF {
+    diffuse: CosPDF,
+    wi: Vec3,
+    exponent: f64,
+    spec_weight: f64,
+}
+
+impl GlossyPDF {
+    #[must_use]
+    pub fn new(incident: Vec3, norm: Vec3, exponent: f64, spec_weight: f64) -> Self {
+        Self {
+            diffuse: CosPDF::new(norm),
+            wi: -incident.unit_vector(),
+            exponent,
+            spec_weight: spec_weight.clamp(0.0, 1.0),
+        }
+    }
+
+    fn specular_value(&self, dir: Vec3) -> f64 {
+        let h = (self.wi + dir).unit_vector();
+        // h and -h reflect the same way
+        let cos_h = dot(h, self.diffuse.uvw.w()).abs();
+        let cos_oh = dot(dir, h).abs();
+        if cos_oh <= 0.0 {
+            return 0.0;
+        }
+        (self.exponent + 1.0) / (2.0 * PI) * cos_h.powf(self.exponent) / (4.0 * cos_oh)
+    }
+}
+
+impl From<GlossyPDF> for Arc<dyn PDensityFn + Sync + Send> {
+    fn from(pdf: GlossyPDF) -> Arc<dyn PDensityFn + Sync + Send> {
+        Arc::new(pdf)
+    }
+}
+
+impl PDensityFn for GlossyPDF {
+    fn value(&self, dir: Vec3) -> f64 {
+        let dir = dir.unit_vector();
+        (1.0 - self.spec_weight) * self.diffuse.value(dir)
+            + self.spec_weight * self.specular_value(dir)
+    }
+
+    fn generate(&self, sr: &mut ScatterRecord) -> Vec3 {
+        if random::double() >= self.spec_weight {
+            return self.diffuse.generate(sr);
+        }
+        let cos_h = random::double().powf(1.0 / (self.exponent + 1.0));
+        let sin_h = f64::sqrt(f64::max(0.0, 1.0 - cos_h * cos_h));
+        let phi = 2.0 * PI * random::double();
+        let h = self.diffuse.uvw.local(
+            sin_h * f64::cos(phi), sin_h * f64::sin(phi), cos_h,
+        );
+        vec3::reflect(-self.wi, h)
+    }
+}
+
 pub struct PhongSpecularPDF {
     incident: Vec3,
     pub uvw: OrthoNormalBasis,
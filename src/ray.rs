@@ -1,9 +1,12 @@
 use crate::vec3::{Vec3,Point3};
+use crate::spectrum::Wavelengths;
 
 pub struct Ray {
     pub origin : Point3,
     pub dir : Vec3,
     pub time : f64,
+    // Only set when rendering spectrally
+    pub wavelengths : Option<Wavelengths>,
 }
 
 impl Ray {
@@ -11,9 +14,16 @@ impl Ray {
     pub fn new(origin: Point3, dir: Vec3, time: f64) -> Self {
         Self {
             origin, dir, time,
+            wavelengths: None,
         }
     }
 
+    #[must_use]
+    pub fn with_wavelengths(mut self, wavelengths: Option<Wavelengths>) -> Self {
+        self.wavelengths = wavelengths;
+        self
+    }
+
     #[must_use]
     pub fn at(&self, t : f64) -> Point3 {
         self.origin + t * self.dir
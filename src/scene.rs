@@ -1,15 +1,17 @@
 use crate::vec3::{Point3,Color};
 use crate::hit::Hittable;
+use crate::hittable_list::HittableList;
 
 use std::sync::Arc;
 
+#[derive(Clone)]
 pub struct Scene {
     pub lookfrom: Point3,
     pub lookat: Point3,
     pub background: Color,
     pub vfov: f64,
     pub world: Arc<dyn Hittable + Sync + Send>,
-    pub lights: Arc<dyn Hittable + Sync + Send>,
+    pub lights: Arc<HittableList>,
 }
 
 pub mod defs {
@@ -20,7 +22,7 @@ pub mod defs {
     use crate::material::{
         Dielectric, DiffuseLight, Lambertian,
         Material, Metal, Corroded,
-        AnisotropicPhong,
+        AnisotropicPhong, HenyeyGreenstein, Subsurface, Dispersion, Layered, Mix, AlphaCutout, OrenNayar, Burley, Sheen, SheenLayer,
     };
     use crate::sphere::Sphere;
     use crate::cylinder::Cylinder;
@@ -32,6 +34,15 @@ pub mod defs {
     use crate::boxx::Boxx;
     use crate::moving_sphere::MovingSphere;
     use crate::constant_medium::ConstantMedium;
+    use crate::heterogeneous_medium::{HeterogeneousMedium, NoiseDensity, DensityGrid};
+    use crate::aabb::AABB;
+    use crate::medium::{HomogeneousMedium, MediumBoundary, MediumInterface};
+    use crate::thin_film::ThinFilm;
+    use crate::merl::Merl;
+    use crate::curve::Strands;
+    use crate::hair::Hair;
+    use crate::transform::{Mat4, Transform, AnimatedTransform};
+    use std::path::Path;
     use crate::util::random;
     use crate::obj::WfObject;
     use std::sync::Arc;
@@ -506,6 +517,155 @@ pub mod defs {
         }
     }
 
+    #[must_use]
+    pub fn cornell_cloud() -> Scene {
+        let cbox = empty_cornell_box();
+
+        let cloud: Arc<dyn Hittable + Sync + Send> = HeterogeneousMedium::new(
+            AABB {
+                min: Point3(60.0, 330.0, 120.0),
+                max: Point3(495.0, 500.0, 480.0),
+            },
+            NoiseDensity::new(0.05, 3.0).into(),
+            Color(1.0, 1.0, 1.0),
+        ).into();
+
+        // A smoke plume that spreads out and thins as it rises
+        let plume_density = DensityGrid::from_fn(32, 64, 32, |p| {
+            let spread = 0.05 + 0.3 * p.y();
+            let r2 = (p.x() - 0.5) * (p.x() - 0.5) + (p.z() - 0.5) * (p.z() - 0.5);
+            0.04 * (1.0 - p.y()) * f64::exp(-r2 / (spread * spread))
+        });
+        let plume: Arc<dyn Hittable + Sync + Send> = HeterogeneousMedium::new(
+            AABB {
+                min: Point3(180.0, 0.0, 180.0),
+                max: Point3(380.0, 320.0, 380.0),
+            },
+            plume_density.into(),
+            Color(0.4, 0.4, 0.4),
+        ).into();
+
+        let world = HittableList::new(vec![
+            cloud, plume,
+            cbox.world,
+        ]);
+
+        Scene {
+            lookfrom: cbox.lookfrom,
+            lookat: cbox.lookat,
+            background: cbox.background,
+            vfov: cbox.vfov,
+            world: world.into(),
+            lights: cbox.lights,
+        }
+    }
+
+    // Forward scattering fog, so the lights get a visible glow around them
+    #[must_use]
+    pub fn foggy_lights() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(13.0, 3.0, 3.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 40.0;
+
+        let ground: Arc<dyn Material + Sync + Send> = Lambertian::from_texture(
+            texture::Checker::new(Color(0.2, 0.3, 0.1), Color(0.9, 0.9, 0.9)).into()
+        ).into();
+
+        let turq_sphere: Arc<dyn Hittable + Sync + Send> = Sphere::new(
+            Point3(-2.0, 4.0, -3.0), 0.5,
+            DiffuseLight::new(Color(0.0, 40.0, 30.0)).into(),
+        ).into();
+        let red_sphere: Arc<dyn Hittable + Sync + Send> = Sphere::new(
+            Point3(-1.0, 3.0, 3.0), 0.5,
+            DiffuseLight::new(Color(40.0, 0.0, 15.0)).into(),
+        ).into();
+
+        let boundary: Arc<dyn Hittable + Sync + Send> = Sphere::new(
+            Point3::new(), 50.0,
+            Dielectric::new(1.5, 0.0, Color(1.0, 1.0, 1.0)).into(),
+        ).into();
+        let fog: Arc<dyn Hittable + Sync + Send> = ConstantMedium::with_phase(
+            boundary, 0.02,
+            HenyeyGreenstein::double(Color(1.0, 1.0, 1.0), 0.8, -0.3, 0.9).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(Point3(0.0, -1000.0, 0.0), 1000.0, ground).into(),
+            Sphere::new(
+                Point3(0.0, 1.0, 0.0), 1.0,
+                Metal::new(Color(0.7, 0.6, 0.5), 0.1).into(),
+            ).into(),
+            turq_sphere.clone(),
+            red_sphere.clone(),
+            fog,
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: objects.into(),
+            lights: HittableList::new(vec![turq_sphere, red_sphere]).into(),
+        }
+    }
+
+    // Ice in water in a glass bowl. The spheres overlap completely, and the
+    // priorities carve the glass down to a shell and the water around the ice.
+    #[must_use]
+    pub fn nested_dielectrics() -> Scene {
+        let cbox = empty_cornell_box();
+        let center = Point3(278.0, 150.0, 278.0);
+
+        let glass: Arc<dyn Hittable + Sync + Send> = MediumBoundary::new(
+            Sphere::new(
+                center, 150.0,
+                Dielectric::new(1.5, 0.0, Color(1.0, 1.0, 1.0)).into(),
+            ).into(),
+            MediumInterface::new(None, 1.5, 1),
+        ).into();
+
+        let water: Arc<dyn Hittable + Sync + Send> = MediumBoundary::new(
+            Sphere::new(
+                center, 140.0,
+                Dielectric::new(1.33, 0.0, Color(1.0, 1.0, 1.0)).into(),
+            ).into(),
+            MediumInterface::new(
+                Some(HomogeneousMedium::absorbing(
+                    Color(0.004, 0.0012, 0.0008)
+                ).into()),
+                1.33, 2,
+            ),
+        ).into();
+
+        let ice: Arc<dyn Hittable + Sync + Send> = MediumBoundary::new(
+            Sphere::new(
+                center + Vec3(-30.0, 40.0, 0.0), 60.0,
+                Dielectric::new(1.31, 0.0, Color(1.0, 1.0, 1.0)).into(),
+            ).into(),
+            MediumInterface::new(
+                Some(HomogeneousMedium::new(
+                    Color(0.0005, 0.0003, 0.0001),
+                    Color(0.02, 0.02, 0.02),
+                    0.6,
+                ).into()),
+                1.31, 3,
+            ),
+        ).into();
+
+        let world = HittableList::new(vec![
+            glass, water, ice,
+            cbox.world,
+        ]);
+
+        Scene {
+            lookfrom: cbox.lookfrom,
+            lookat: cbox.lookat,
+            background: cbox.background,
+            vfov: cbox.vfov,
+            world: world.into(),
+            lights: cbox.lights,
+        }
+    }
+
     #[must_use]
     pub fn fancy_random_scene() -> Scene {
         let background = Color(0.0, 0.0, 0.0);
@@ -884,6 +1044,537 @@ pub mod defs {
         }
     }
 
+    // Skin, wax, marble and milk, each as a single Subsurface material
+    #[must_use]
+    pub fn subsurface_spheres() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 4.0, 14.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let skin: Arc<dyn Material + Sync + Send> = Subsurface::new(
+            1.4, Color(0.9, 0.6, 0.5), Color(0.35, 0.12, 0.08), 0.0,
+        ).into();
+        let wax: Arc<dyn Material + Sync + Send> = Subsurface::new(
+            1.45, Color(0.95, 0.9, 0.6), Color(0.2, 0.15, 0.08), 0.3,
+        ).into();
+        let marble: Arc<dyn Material + Sync + Send> = Subsurface::new(
+            1.5, Color(0.99, 0.99, 0.98), Color(0.05, 0.05, 0.06), 0.0,
+        ).into();
+        let milk: Arc<dyn Material + Sync + Send> = Subsurface::new(
+            1.35, Color(0.999, 0.998, 0.99), Color(0.1, 0.08, 0.05), 0.8,
+        ).into();
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -3.0, 3.0, -3.0, 3.0, 8.0,
+            DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Sphere::new(Point3(-4.5, 1.0, 0.0), 1.0, skin).into(),
+            Sphere::new(Point3(-1.5, 1.0, 0.0), 1.0, wax).into(),
+            Sphere::new(Point3(1.5, 1.0, 0.0), 1.0, marble).into(),
+            Sphere::new(Point3(4.5, 1.0, 0.0), 1.0, milk).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Dispersive glass & diamond under a small, bright light. Only really
+    // interesting with --spectral, where the caustics split into rainbows.
+    #[must_use]
+    pub fn dispersion() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 6.0, 14.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -0.5, 0.5, -6.5, -5.5, 8.0,
+            DiffuseLight::new(Color(150.0, 150.0, 150.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Sphere::new(
+                Point3(-3.0, 1.0, 0.0), 1.0,
+                Dielectric::dispersive(Dispersion::SF11, 0.0, Color(1.0, 1.0, 1.0)).into(),
+            ).into(),
+            Sphere::new(
+                Point3(0.0, 1.0, 0.0), 1.0,
+                Dielectric::dispersive(Dispersion::DIAMOND, 0.0, Color(1.0, 1.0, 1.0)).into(),
+            ).into(),
+            // Far more dispersive than anything real, to make the point
+            Sphere::new(
+                Point3(3.0, 1.0, 0.0), 1.0,
+                Dielectric::dispersive(
+                    Dispersion::Cauchy { a: 1.45, b: 0.05 }, 0.0, Color(1.0, 1.0, 1.0)
+                ).into(),
+            ).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Soap bubbles, an anti-reflection coated lens, anodized titanium, and
+    // an oil slick on a dark puddle
+    #[must_use]
+    pub fn thin_film() -> Scene {
+        let background = Color(0.70, 0.80, 1.00);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        // A bubble is just a film of soapy water with air on both sides
+        let bubble = || -> Arc<dyn Material + Sync + Send> {
+            Dielectric::new(1.0, 0.0, Color(1.0, 1.0, 1.0))
+                .with_film(ThinFilm::from_texture(
+                    texture::NoiseFloat::new(1.5, 200.0, 900.0).into(), 1.33,
+                ))
+                .into()
+        };
+
+        let objects = HittableList::new(vec![
+            // oil on a dark puddle
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Metal::new(Color(0.05, 0.05, 0.05), 0.0)
+                    .with_film(ThinFilm::from_texture(
+                        texture::NoiseFloat::new(0.5, 300.0, 1200.0).into(), 1.47,
+                    ))
+                    .into(),
+            ).into(),
+            Sphere::new(Point3(-3.2, 1.2, 0.0), 1.2, bubble()).into(),
+            Sphere::new(Point3(-1.4, 2.4, -2.0), 0.6, bubble()).into(),
+            // quarter-wave MgF2 coating on crown glass
+            Sphere::new(
+                Point3(0.0, 1.0, 0.0), 1.0,
+                Dielectric::new(1.52, 0.0, Color(1.0, 1.0, 1.0))
+                    .with_film(ThinFilm::new(100.0, 1.38))
+                    .into(),
+            ).into(),
+            // titanium dioxide on titanium
+            Sphere::new(
+                Point3(2.8, 1.0, 0.0), 1.0,
+                Metal::new(Color(0.55, 0.5, 0.45), 0.05)
+                    .with_film(ThinFilm::new(250.0, 2.4))
+                    .into(),
+            ).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![]).into(),
+        }
+    }
+
+    // Clearcoats: car paint, varnished wood, and a satin coat over plastic
+    #[must_use]
+    pub fn clearcoat() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 4.0, 14.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let car_paint: Arc<dyn Material + Sync + Send> = Layered::new(
+            Metal::new(Color(0.7, 0.05, 0.05), 0.4).into(), 1.5, 0.0,
+        ).into();
+        let varnished_wood: Arc<dyn Material + Sync + Send> = Layered::new(
+            Lambertian::from_texture(
+                texture::Wood::new(Vec3(4.0, 0.1, 1.0), Color(0.7, 0.3, 0.1)).into()
+            ).into(),
+            1.5, 0.0,
+        ).with_absorption(Color(0.9, 0.7, 0.4), 0.2).into();
+        let satin: Arc<dyn Material + Sync + Send> = Layered::new(
+            Lambertian::new(Color(0.1, 0.3, 0.7)).into(), 1.5, 0.3,
+        ).into();
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -3.0, 3.0, -3.0, 3.0, 8.0,
+            DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Sphere::new(Point3(-3.0, 1.0, 0.0), 1.0, car_paint).into(),
+            Sphere::new(Point3(0.0, 1.0, 0.0), 1.0, varnished_wood).into(),
+            Sphere::new(Point3(3.0, 1.0, 0.0), 1.0, satin).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Rusty gold blended by noise, and a cutout card in front of it
+    #[must_use]
+    pub fn material_blend() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let rusty_gold: Arc<dyn Material + Sync + Send> = Mix::new(
+            Metal::new(Color(0.8, 0.6, 0.2), 0.1).into(),
+            Lambertian::new(Color(0.4, 0.15, 0.05)).into(),
+            texture::NoiseFloat::new(3.0, 0.0, 2.0).into(),
+        ).into();
+
+        let card: Arc<dyn Material + Sync + Send> = AlphaCutout::new(
+            Lambertian::new(Color(0.2, 0.6, 0.2)).into(),
+            texture::CheckerBump::new(1.0, 0.0).into(),
+            0.5,
+        ).into();
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -3.0, 3.0, -3.0, 3.0, 8.0,
+            DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Sphere::new(Point3(-1.5, 1.0, 0.0), 1.0, rusty_gold).into(),
+            AARect::xy_rect(0.5, 3.5, 0.0, 2.5, 1.0, card).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Lambertian vs. Oren-Nayar vs. Burley, lit from behind the camera where
+    // rough surfaces look flattest
+    #[must_use]
+    pub fn rough_diffuse() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let clay = Color(0.75, 0.45, 0.3);
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xy_rect(
+            -2.0, 2.0, 3.0, 6.0, 14.0,
+            DiffuseLight::new(Color(15.0, 15.0, 15.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                OrenNayar::new(WHITE, 0.3).into(),
+            ).into(),
+            Sphere::new(Point3(-2.5, 1.0, 0.0), 1.0, Lambertian::new(clay).into()).into(),
+            Sphere::new(Point3(0.0, 1.0, 0.0), 1.0, OrenNayar::new(clay, 0.8).into()).into(),
+            Sphere::new(Point3(2.5, 1.0, 0.0), 1.0, Burley::new(clay, 1.0).into()).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Velvet, felt, and sheen on its own, with a rim light behind them
+    #[must_use]
+    pub fn fabric() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let velvet: Arc<dyn Material + Sync + Send> = SheenLayer::new(
+            Lambertian::new(Color(0.3, 0.02, 0.05)).into(),
+            Sheen::new(Color(1.0, 0.6, 0.7), 0.3),
+        ).into();
+        let felt: Arc<dyn Material + Sync + Send> = SheenLayer::new(
+            OrenNayar::new(Color(0.1, 0.2, 0.5), 0.6).into(),
+            Sheen::new(Color(0.6, 0.7, 1.0), 0.8),
+        ).into();
+        let sheen: Arc<dyn Material + Sync + Send> =
+            Sheen::new(Color(1.0, 1.0, 1.0), 0.5).into();
+
+        let lights: Vec<Arc<dyn Hittable + Sync + Send>> = vec![
+            AARect::xz_rect(
+                -3.0, 3.0, -3.0, 3.0, 8.0,
+                DiffuseLight::new(Color(3.0, 3.0, 3.0)).into(),
+            ).into(),
+            AARect::xy_rect(
+                -4.0, 4.0, 1.0, 4.0, -6.0,
+                DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+            ).into(),
+        ];
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Sphere::new(Point3(-2.5, 1.0, 0.0), 1.0, velvet).into(),
+            Sphere::new(Point3(0.0, 1.0, 0.0), 1.0, felt).into(),
+            Sphere::new(Point3(2.5, 1.0, 0.0), 1.0, sheen).into(),
+            FlipFace::new(lights[0].clone()).into(),
+            lights[1].clone(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(lights).into(),
+        }
+    }
+
+    // A measured BRDF next to a couple of analytic ones, for comparison
+    #[must_use]
+    pub fn measured_brdf<P: AsRef<Path>>(fname: P) -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let measured: Arc<dyn Material + Sync + Send> = Merl::new(fname).into();
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -3.0, 3.0, -3.0, 3.0, 8.0,
+            DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Sphere::new(
+                Point3(-2.5, 1.0, 0.0), 1.0,
+                Layered::new(Lambertian::new(Color(0.2, 0.3, 0.6)).into(), 1.5, 0.1).into(),
+            ).into(),
+            Sphere::new(Point3(0.0, 1.0, 0.0), 1.0, measured).into(),
+            Sphere::new(
+                Point3(2.5, 1.0, 0.0), 1.0,
+                Metal::new(Color(0.6, 0.6, 0.65), 0.2).into(),
+            ).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Tufts of blond, brown and red hair, colored by melanin concentration
+    #[must_use]
+    pub fn hair() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.2, 0.0);
+        let vfov = 30.0;
+
+        let tuft = |center: Point3, n: usize| {
+            let strands: Vec<(f64, f64, Vec<Point3>)> = (0..n).map(|_| {
+                let root = center + Vec3(
+                    random::double_range(-0.5, 0.5), 0.0, random::double_range(-0.5, 0.5)
+                );
+                let height = random::double_range(2.0, 2.6);
+                // lean away from the middle of the tuft, and droop a bit
+                let lean = 0.6 * (root - center) + Vec3(
+                    random::double_range(-0.1, 0.1), 0.0, random::double_range(-0.1, 0.1)
+                );
+                let points = vec![
+                    root,
+                    root + Vec3(0.0, height / 3.0, 0.0) + 0.3 * lean,
+                    root + Vec3(0.0, 2.0 * height / 3.0, 0.0) + lean,
+                    root + Vec3(0.0, 0.8 * height, 0.0) + 2.5 * lean,
+                ];
+                (0.02, 0.006, points)
+            }).collect();
+            strands
+        };
+
+        let blond: Arc<dyn Material + Sync + Send> =
+            Hair::from_melanin(0.3, 0.0, 0.3, 0.3).into();
+        let brown: Arc<dyn Material + Sync + Send> =
+            Hair::from_melanin(1.3, 0.0, 0.3, 0.3).into();
+        let red: Arc<dyn Material + Sync + Send> =
+            Hair::from_melanin(0.4, 1.5, 0.3, 0.3).into();
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -3.0, 3.0, -3.0, 3.0, 8.0,
+            DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            Strands::from_strands(&tuft(Point3(-2.5, 0.0, 0.0), 400), blond).into(),
+            Strands::from_strands(&tuft(Point3(0.0, 0.0, 0.0), 400), brown).into(),
+            Strands::from_strands(&tuft(Point3(2.5, 0.0, 0.0), 400), red).into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // One small model, instanced a few thousand times with random rotation,
+    // scale and shear, under a squashed (ellipsoidal) light
+    #[must_use]
+    pub fn instancing() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 18.0, 30.0);
+        let lookat = Point3(0.0, 0.0, 0.0);
+        let vfov = 40.0;
+
+        let model: Arc<dyn Hittable + Sync + Send> = BVHNode::new(&HittableList::new(vec![
+            Boxx::new(
+                Point3(-0.5, 0.0, -0.5), Point3(0.5, 0.6, 0.5),
+                Lambertian::new(Color(0.7, 0.3, 0.2)).into(),
+            ).into(),
+            Sphere::new(
+                Point3(0.0, 0.9, 0.0), 0.3,
+                Metal::new(Color(0.8, 0.8, 0.9), 0.1).into(),
+            ).into(),
+        ]), 0.0, 1.0).into();
+
+        let n = 24;
+        let mut instances = HittableList::default();
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..4 {
+                    let center = Vec3(
+                        1.5 * (f64::from(i) - f64::from(n) / 2.0) + random::double_range(-0.3, 0.3),
+                        1.5 * f64::from(k),
+                        1.5 * (f64::from(j) - f64::from(n) / 2.0) + random::double_range(-0.3, 0.3),
+                    );
+                    let m = Mat4::translate(center)
+                        * Mat4::rotate(Vec3::random_unit_vector(), random::double_range(0.0, 360.0))
+                        * Mat4::shear(random::double_range(-0.3, 0.3), 0.0, 0.0, 0.0, 0.0, 0.0)
+                        * Mat4::scale(Vec3(
+                            random::double_range(0.4, 0.8),
+                            random::double_range(0.4, 1.0),
+                            random::double_range(0.4, 0.8),
+                        ));
+                    instances.add(Transform::new(model.clone(), m).into());
+                }
+            }
+        }
+        eprintln!("instances: {}", instances.objects.len());
+
+        let light: Arc<dyn Hittable + Sync + Send> = Transform::new(
+            Sphere::new(
+                Point3(0.0, 0.0, 0.0), 1.0,
+                DiffuseLight::new(Color(8.0, 8.0, 8.0)).into(),
+            ).into(),
+            Mat4::translate(Vec3(0.0, 16.0, 0.0)) * Mat4::scale(Vec3(6.0, 0.5, 3.0)),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            BVHNode::new(&instances, 0.0, 1.0).into(),
+            light.clone(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
+    // Motion blur on things other than spheres: a spinning box, a box that
+    // tumbles along a curved path through several keyframes, and one that
+    // grows
+    #[must_use]
+    pub fn motion_blur() -> Scene {
+        let background = Color(0.0, 0.0, 0.0);
+        let lookfrom = Point3(0.0, 3.0, 12.0);
+        let lookat = Point3(0.0, 1.0, 0.0);
+        let vfov = 30.0;
+
+        let cube: Arc<dyn Hittable + Sync + Send> = Boxx::new(
+            Point3(-0.5, -0.5, -0.5), Point3(0.5, 0.5, 0.5),
+            Lambertian::new(Color(0.7, 0.3, 0.2)).into(),
+        ).into();
+
+        let spinning = AnimatedTransform::between(
+            cube.clone(),
+            0.0, Mat4::translate(Vec3(-2.5, 1.0, 0.0)) * Mat4::rotate_y(0.0),
+            1.0, Mat4::translate(Vec3(-2.5, 1.0, 0.0)) * Mat4::rotate_y(90.0),
+        );
+        let tumbling = AnimatedTransform::new(cube.clone(), &[
+            (0.0, Mat4::translate(Vec3(-0.6, 0.6, 0.0))),
+            (0.5, Mat4::translate(Vec3(0.0, 1.6, 0.0)) * Mat4::rotate_z(-60.0)),
+            (1.0, Mat4::translate(Vec3(0.6, 0.6, 0.0)) * Mat4::rotate_z(-120.0)),
+        ]);
+        let growing = AnimatedTransform::between(
+            cube,
+            0.0, Mat4::translate(Vec3(2.5, 0.5, 0.0)) * Mat4::uniform_scale(0.5),
+            1.0, Mat4::translate(Vec3(2.5, 1.0, 0.0)) * Mat4::scale(Vec3(1.0, 2.0, 1.0)),
+        );
+
+        let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+            -3.0, 3.0, -3.0, 3.0, 8.0,
+            DiffuseLight::new(Color(6.0, 6.0, 6.0)).into(),
+        ).into();
+
+        let objects = HittableList::new(vec![
+            Sphere::new(
+                Point3(0.0, -1000.0, 0.0), 1000.0,
+                Lambertian::new(WHITE).into(),
+            ).into(),
+            spinning.into(),
+            tumbling.into(),
+            growing.into(),
+            FlipFace::new(light.clone()).into(),
+        ]);
+
+        Scene {
+            lookfrom, lookat, background, vfov,
+            world: BVHNode::new(&objects, 0.0, 1.0).into(),
+            lights: HittableList::new(vec![light]).into(),
+        }
+    }
+
     #[must_use]
     pub fn solids() -> Scene {
         let background = Color(0.0, 0.0, 0.0);
@@ -1290,3 +1981,110 @@ pub mod defs {
         }
     }
 }
+
+// Scenes that change over time, for rendering frame sequences
+pub mod anims {
+    use crate::scene::{Scene, defs};
+    use crate::vec3::{Point3,Color,Vec3};
+    use crate::material::{DiffuseLight, Lambertian, Metal};
+    use crate::sphere::Sphere;
+    use crate::hit::{Hittable, FlipFace};
+    use crate::aarect::AARect;
+    use crate::hittable_list::HittableList;
+    use crate::bvh::BVHNode;
+    use crate::boxx::Boxx;
+    use crate::camera::CameraView;
+    use crate::animation::{
+        AnimatedScene, CameraAnimation, Interpolation, Track, TransformTrack,
+    };
+    use std::sync::Arc;
+
+    const WHITE: Color  = Color(0.73, 0.73, 0.73);
+
+    // Once around the rough diffuse spheres in four seconds
+    #[must_use]
+    pub fn turntable() -> AnimatedScene {
+        let scene = defs::rough_diffuse();
+        AnimatedScene {
+            camera: CameraAnimation::turntable(
+                &CameraView::from_scene(&scene, 10.0), 0.25, 4.0
+            ),
+            build: Box::new(move |_, _| scene.clone()),
+        }
+    }
+
+    // A cube hops across the floor, tumbling as it goes, while the camera
+    // dollies in and pulls focus to follow it
+    #[must_use]
+    pub fn hopping_cube() -> AnimatedScene {
+        let hops = 3;
+        let hop_time = 1.0;
+        let mut translate = Track::new(Interpolation::CatmullRom);
+        let mut rotate = Track::new(Interpolation::Smooth);
+        for h in 0..=hops {
+            let t = f64::from(h) * hop_time;
+            let x = -3.0 + 2.0 * f64::from(h);
+            translate = translate
+                .key(t, Vec3(x, 0.5, 0.0))
+                .key(t + 0.5 * hop_time, Vec3(x + 1.0, 2.0, 0.0));
+            rotate = rotate.key(t, Vec3(0.0, 0.0, -90.0 * f64::from(h)));
+        }
+        let cube_track = TransformTrack {
+            translate,
+            rotate,
+            ..TransformTrack::default()
+        };
+
+        let end = f64::from(hops) * hop_time;
+        let camera = CameraAnimation {
+            lookfrom: Track::new(Interpolation::Smooth)
+                .key(0.0, Point3(0.0, 3.0, 14.0))
+                .key(end, Point3(2.0, 2.0, 8.0)),
+            lookat: Track::new(Interpolation::Linear)
+                .key(0.0, Point3(-3.0, 1.0, 0.0))
+                .key(end, Point3(3.0, 0.5, 0.0)),
+            vfov: Track::constant(30.0),
+            focus_dist: Track::new(Interpolation::Linear)
+                .key(0.0, 14.0)
+                .key(end, 6.0),
+        };
+
+        let cube: Arc<dyn Hittable + Sync + Send> = Boxx::new(
+            Point3(-0.5, -0.5, -0.5), Point3(0.5, 0.5, 0.5),
+            Lambertian::new(Color(0.7, 0.3, 0.2)).into(),
+        ).into();
+
+        AnimatedScene {
+            camera,
+            build: Box::new(move |time0, time1| {
+                let light: Arc<dyn Hittable + Sync + Send> = AARect::xz_rect(
+                    -4.0, 4.0, -3.0, 3.0, 8.0,
+                    DiffuseLight::new(Color(5.0, 5.0, 5.0)).into(),
+                ).into();
+
+                let objects = HittableList::new(vec![
+                    Sphere::new(
+                        Point3(0.0, -1000.0, 0.0), 1000.0,
+                        Lambertian::new(WHITE).into(),
+                    ).into(),
+                    Sphere::new(
+                        Point3(0.0, 1.0, -3.0), 1.0,
+                        Metal::new(Color(0.8, 0.8, 0.9), 0.05).into(),
+                    ).into(),
+                    cube_track.apply(cube.clone(), time0, time1).into(),
+                    FlipFace::new(light.clone()).into(),
+                ]);
+
+                Scene {
+                    lookfrom: Point3(0.0, 3.0, 14.0),
+                    lookat: Point3(0.0, 1.0, 0.0),
+                    background: Color(0.0, 0.0, 0.0),
+                    vfov: 30.0,
+                    world: BVHNode::new(&objects, time0, time1).into(),
+                    lights: HittableList::new(vec![light]).into(),
+                }
+            }),
+        }
+    }
+
+}
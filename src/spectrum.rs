@@ -0,0 +1,220 @@
+use crate::vec3::{Vec3,Color,dot,cross};
+use crate::util::random;
+
+use std::sync::OnceLock;
+
+// Visible range we sample wavelengths from, in nm
+pub const LAMBDA_MIN : f64 = 380.0;
+pub const LAMBDA_MAX : f64 = 720.0;
+
+// Number of wavelengths carried by each path. Spectral radiance is stored in
+// a Color, one wavelength per channel, so the integrator doesn't need to
+// care which mode it's in.
+pub const N_SAMPLES : usize = 3;
+
+// Piecewise Gaussian used by the CIE fit below
+fn gaussian(x: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (x - mu) / if x < mu { sigma1 } else { sigma2 };
+    f64::exp(-0.5 * t * t)
+}
+
+// CIE 1931 color matching functions, using the multi-lobe fit from Wyman,
+// Sloan & Shirley, "Simple Analytic Approximations to the CIE XYZ Color
+// Matching Functions" (JCGT 2013).
+#[must_use]
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3(
+        1.056 * gaussian(lambda, 599.8, 37.9, 31.0)
+            + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * gaussian(lambda, 568.8, 46.9, 40.5)
+            + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * gaussian(lambda, 437.0, 11.8, 36.0)
+            + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+// XYZ to linear sRGB (D65 white point)
+#[must_use]
+pub fn xyz_to_rgb(xyz: Vec3) -> Color {
+    Color(
+        3.2406 * xyz.x() - 1.5372 * xyz.y() - 0.4986 * xyz.z(),
+        -0.9689 * xyz.x() + 1.8758 * xyz.y() + 0.0415 * xyz.z(),
+        0.0557 * xyz.x() - 0.2040 * xyz.y() + 1.0570 * xyz.z(),
+    )
+}
+
+fn smoothstep(e0: f64, e1: f64, x: f64) -> f64 {
+    let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Smooth "blue", "green" and "red" basis spectra. They sum to one everywhere,
+// so a constant RGB maps to a constant spectrum, and a reflectance in [0,1]
+// stays in [0,1] as long as it's inside the gamut.
+fn basis(lambda: f64) -> Vec3 {
+    let b = 1.0 - smoothstep(480.0, 510.0, lambda);
+    let r = smoothstep(570.0, 600.0, lambda);
+    Vec3(r, 1.0 - r - b, b)
+}
+
+// Everything needed to go between RGB and spectra, computed once up front
+struct Tables {
+    // 1 / integral of y-bar, so that a constant spectrum of 1 has Y = 1
+    y_norm: f64,
+    // Per-channel scale mapping the equal-energy white to RGB (1,1,1). The
+    // renderer treats RGB (1,1,1) as "white", so spectral mode should too.
+    white: Color,
+    // Same as white, but for the coarser steps used by spectrum_to_rgb
+    white_coarse: Color,
+    // Inverse of the matrix taking basis weights to (white balanced) RGB
+    rgb_to_basis: [Vec3; 3],
+}
+
+fn white_balance(c: Color, white: Color) -> Color {
+    Color(c.r() / white.r(), c.g() / white.g(), c.b() / white.b())
+}
+
+// Midpoint rule over the visible range
+fn integrate<F: Fn(f64) -> Vec3>(f: F, step: f64) -> Vec3 {
+    let mut sum = Vec3(0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN + 0.5 * step;
+    while lambda < LAMBDA_MAX {
+        sum += step * f(lambda);
+        lambda += step;
+    }
+    sum
+}
+
+// Step size (nm) for spectrum_to_rgb, which runs during rendering
+const COARSE_STEP : f64 = 10.0;
+
+// Invert the 3x3 matrix with columns c0, c1, c2. The rows of the inverse are
+// the cross products of the columns, over the determinant.
+fn invert(c0: Vec3, c1: Vec3, c2: Vec3) -> [Vec3; 3] {
+    let det = dot(c0, cross(c1, c2));
+    [
+        cross(c1, c2) / det,
+        cross(c2, c0) / det,
+        cross(c0, c1) / det,
+    ]
+}
+
+fn tables() -> &'static Tables {
+    static TABLES : OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let y_norm = 1.0 / integrate(cie_xyz, 1.0).y();
+        let white = xyz_to_rgb(y_norm * integrate(cie_xyz, 1.0));
+        let white_coarse = xyz_to_rgb(y_norm * integrate(cie_xyz, COARSE_STEP));
+
+        // RGB of each basis spectrum, i.e. the columns of basis -> RGB
+        let cols: Vec<Color> = (0..3).map(|i| {
+            let xyz = integrate(|l| basis(l)[i] * cie_xyz(l), 1.0);
+            white_balance(xyz_to_rgb(y_norm * xyz), white)
+        }).collect();
+
+        Tables {
+            y_norm, white, white_coarse,
+            rgb_to_basis: invert(cols[0], cols[1], cols[2]),
+        }
+    })
+}
+
+fn basis_weights(c: Color) -> Vec3 {
+    let t = tables();
+    Vec3(
+        dot(t.rgb_to_basis[0], c),
+        dot(t.rgb_to_basis[1], c),
+        dot(t.rgb_to_basis[2], c),
+    )
+}
+
+// Value at lambda of the spectrum we use in place of RGB c
+#[must_use]
+pub fn rgb_to_spectrum(c: Color, lambda: f64) -> f64 {
+    f64::max(dot(basis_weights(c), basis(lambda)), 0.0)
+}
+
+// RGB for a spectrum (e.g. a reflectance) given as a function of wavelength.
+// Constant spectra map to gray, same as the other direction.
+#[must_use]
+pub fn spectrum_to_rgb<F: Fn(f64) -> f64>(f: F) -> Color {
+    let t = tables();
+    let xyz = integrate(|l| f(l) * cie_xyz(l), COARSE_STEP);
+    white_balance(xyz_to_rgb(t.y_norm * xyz), t.white_coarse)
+}
+
+// The wavelengths carried by a single path. The first is the "hero", which
+// decides wavelength-dependent directions (e.g. dispersion). The rest are
+// spread evenly across the visible range from it, as in Wilkie et al.,
+// "Hero Wavelength Spectral Sampling" (EGSR 2014).
+#[derive(Copy, Clone, Debug)]
+pub struct Wavelengths {
+    pub lambda: [f64; N_SAMPLES],
+    pub terminated: bool,
+}
+
+impl Wavelengths {
+    #[must_use]
+    pub fn sample() -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = random::double() * range;
+        let mut lambda = [0.0; N_SAMPLES];
+        for (i, l) in lambda.iter_mut().enumerate() {
+            let offset = (hero + range * i as f64 / N_SAMPLES as f64) % range;
+            *l = LAMBDA_MIN + offset;
+        }
+        Self { lambda, terminated: false }
+    }
+
+    #[must_use]
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // Value of the spectrum for RGB c at each of our wavelengths
+    #[must_use]
+    pub fn spectrum(&self, c: Color) -> Color {
+        let w = basis_weights(c);
+        let mut s = Color(0.0, 0.0, 0.0);
+        for (i, l) in self.lambda.iter().enumerate() {
+            s[i] = f64::max(dot(w, basis(*l)), 0.0);
+        }
+        s
+    }
+
+    // Value of a spectrum, given as a function of wavelength, at each of our
+    // wavelengths
+    #[must_use]
+    pub fn eval<F: Fn(f64) -> f64>(&self, f: F) -> Color {
+        let mut s = Color(0.0, 0.0, 0.0);
+        for (i, l) in self.lambda.iter().enumerate() {
+            s[i] = f(*l);
+        }
+        s
+    }
+
+    // Once the path direction depends on the hero wavelength, the others
+    // can't come along for the ride. Drop them and weight up the hero to
+    // account for the lower sample count. Returns the weight to apply.
+    #[must_use]
+    pub fn terminate_secondary(&mut self) -> Color {
+        if self.terminated {
+            return Color(1.0, 1.0, 1.0);
+        }
+        self.terminated = true;
+        Color(N_SAMPLES as f64, 0.0, 0.0)
+    }
+
+    // Convert radiance sampled at our wavelengths back to linear RGB
+    #[must_use]
+    pub fn to_rgb(&self, s: Color) -> Color {
+        let t = tables();
+        let pdf = 1.0 / (LAMBDA_MAX - LAMBDA_MIN);
+        let mut xyz = Vec3(0.0, 0.0, 0.0);
+        for (i, l) in self.lambda.iter().enumerate() {
+            xyz += s[i] * cie_xyz(*l) / pdf;
+        }
+        white_balance(xyz_to_rgb(t.y_norm * xyz / N_SAMPLES as f64), t.white)
+    }
+}
@@ -32,6 +32,10 @@ impl Sphere {
         }
     }
 
+    fn whole(&self) -> bool {
+        self.phi_max >= 2.0 * PI && self.theta_min <= 0.0 && self.theta_max >= PI
+    }
+
     // TODO(oren): these are calculated on the normal vector, need to go back to
     // the book and recall why that is
     fn get_sphere_uv(&self, p: Point3) -> (f64, f64, Vec3, Vec3) {
@@ -129,6 +133,10 @@ impl Hittable for Sphere {
         }
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mat);
+    }
+
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         Some(AABB {
             min: self.center - Vec3(self.radius, self.radius, self.radius),
@@ -154,4 +162,17 @@ impl Hittable for Sphere {
         uvw.build_from_w(direction);
         uvw.local_v(Vec3::random_to_sphere(self.radius, dist_squared))
     }
+
+    // Only whole spheres; partial ones aren't used as lights
+    fn sample_surface(&self) -> Option<(Point3, Vec3)> {
+        if !self.whole() {
+            return None;
+        }
+        let n = Vec3::random_unit_vector();
+        Some((self.center + self.radius * n, n))
+    }
+
+    fn area(&self) -> f64 {
+        if self.whole() { 4.0 * PI * self.radius * self.radius } else { 0.0 }
+    }
 }
@@ -0,0 +1,300 @@
+// Stochastic progressive photon mapping (Hachisuka & Jensen 2009). Each pass
+// follows one camera path per pixel through mirrors and glass to the first
+// diffuse surface it meets, sends a batch of photons out from the lights into
+// a kd-tree, and gathers the photons near each pixel's point. The gather
+// radius shrinks as photons pile up, so the passes converge on the right
+// answer, caustics seen through glass and all.
+//
+// Light from emitters seen directly, or one bounce away, is found from the
+// camera as in the path tracer; the photons bring everything else. So the
+// background only lights things directly, and emitters missing from
+// scene.lights give off no photons. Media are ignored, as in BDPT.
+
+use super::{Lobe, PathState, Radiance, RenderSettings, Tracer, light_index};
+use super::bdpt::Emission;
+
+use hawxide::{Color, INFINITY, PDensityFn, PI, Point3, Ray, Scene, Vec3, pdf, random};
+use hawxide::camera::CameraModel;
+use hawxide::hit::{HitRecord, Hittable};
+use hawxide::kdtree::KdTree;
+use hawxide::material::ScatterRecord;
+use hawxide::vec3::dot;
+use pdf::{HittablePDF, MixturePDF};
+
+use std::io::Write;
+use std::sync::atomic::Ordering;
+use rayon::prelude::*;
+
+// How much of each pass's photons count toward shrinking the radius; the
+// rest of the old estimate is let go
+const ALPHA : f64 = 2.0 / 3.0;
+
+struct Photon {
+    // which way it was going
+    dir: Vec3,
+    beta: Color,
+    // which of scene.lights it came from
+    light: usize,
+}
+
+// Where a camera path met its first diffuse surface
+struct VisiblePoint {
+    hr: HitRecord,
+    r_in: Ray,
+    attenuation: Color,
+    // what makes it back to the camera
+    beta: Color,
+}
+
+impl VisiblePoint {
+    // The BSDF for light arriving from direction w
+    fn f(&self, w: Vec3) -> Color {
+        let cos_theta = dot(self.hr.shading_geo.n, w);
+        if cos_theta <= 1e-6 {
+            return Color(0.0, 0.0, 0.0);
+        }
+        let scattered = Ray::new(self.hr.p, w, self.r_in.time);
+        self.hr.mat.scattering_value(&self.r_in, &self.hr, self.attenuation, &scattered)
+            / cos_theta
+    }
+}
+
+struct PixelState {
+    radius: f64,
+    // photons that have counted so far
+    n: f64,
+    // photon flux, scaled to the current radius
+    tau: Radiance,
+    // light found from the camera, summed over the passes
+    ld: Radiance,
+}
+
+impl PixelState {
+    // Add the photons near vp, and shrink the radius to match. A pass that
+    // comes out NaN or infinite is dropped, as a bad sample would be.
+    fn gather(&mut self, tree: &KdTree<Photon>, vp: &VisiblePoint, tracer: &Tracer) {
+        let n_lights = tracer.n_lights;
+        let mut phi = Radiance::zero(n_lights);
+        let mut m = 0_u32;
+        tree.within(vp.hr.p, self.radius, |_, photon| {
+            m += 1;
+            let light = Some(photon.light).filter(|_| n_lights > 0);
+            phi.add_light(2, vp.beta * vp.f(-photon.dir) * photon.beta, light);
+        });
+        if m == 0 {
+            return;
+        }
+        let total = phi.total();
+        if total.is_nan() || !(total.r() + total.g() + total.b()).is_finite() {
+            tracer.bad_samples.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let m = f64::from(m);
+        let n = self.n + ALPHA * m;
+        let radius = self.radius * (n / (self.n + m)).sqrt();
+        let shrink = (radius / self.radius).powi(2);
+        self.tau = self.tau.clone().add(&phi).scale(Color(shrink, shrink, shrink));
+        self.n = n;
+        self.radius = radius;
+    }
+}
+
+// One sample of the light reaching hr straight from an emitter (or the
+// background), taken as the path tracer takes it
+fn direct(scene: &Scene, r: &Ray, hr: &HitRecord, mut sr: ScatterRecord,
+          path: &PathState, tracer: &Tracer, rad: &mut Radiance) {
+    let light_pdf = if scene.lights.empty() {
+        sr.pdf.clone()
+    } else {
+        HittablePDF::new(scene.lights.clone(), hr.p).into()
+    };
+    let mix_pdf = MixturePDF::new(light_pdf, sr.pdf.clone());
+    let scattered = Ray::new(hr.p, mix_pdf.generate(&mut sr), r.time);
+    let pdf_val = mix_pdf.value(scattered.dir);
+    if pdf_val.is_nan() || pdf_val <= 0.0 || pdf_val.is_infinite() {
+        tracer.bad_pdfs.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    // NOTE: generate may have updated sr.attenuation
+    let value = hr.mat.scattering_value(r, hr, sr.attenuation, &scattered);
+    let lobe = Lobe::of(r, hr, scattered.dir, false);
+    let Some(next) = path.bounce(lobe, value / pdf_val, &tracer.limits) else { return; };
+
+    let Some(lhr) = scene.world.hit(&scattered, 0.001, INFINITY) else {
+        rad.add_emission(&next, scene.background, None);
+        return;
+    };
+    let emitted = lhr.mat.emitted(&scattered, &lhr, lhr.u, lhr.v, lhr.p);
+    if !emitted.near_zero() {
+        let light = if tracer.n_lights > 0 { light_index(scene, &scattered, &lhr) } else { None };
+        rad.add_emission(&next, emitted, light);
+    }
+}
+
+// A direction off hr from its material's own PDF, with the BSDF times cosine
+// over the density that way. None if the PDF gave out.
+fn sample(r: &Ray, hr: &HitRecord, mut sr: ScatterRecord, tracer: &Tracer)
+          -> Option<(Ray, Color)> {
+    let pdf = sr.pdf.clone();
+    let wo = pdf.generate(&mut sr);
+    let pdf_val = pdf.value(wo);
+    if pdf_val.is_nan() || pdf_val <= 0.0 || pdf_val.is_infinite() {
+        tracer.bad_pdfs.fetch_add(1, Ordering::Relaxed);
+        return None;
+    }
+    let scattered = Ray::new(hr.p, wo, r.time);
+    // NOTE: generate may have updated sr.attenuation
+    let value = hr.mat.scattering_value(r, hr, sr.attenuation, &scattered);
+    Some((scattered, value / pdf_val))
+}
+
+// Follow a camera ray through any specular bounces to a diffuse surface,
+// adding up the light found on the way. Materials that can't be evaluated
+// toward the photons (see Material::deterministic) are passed through too.
+fn camera_path(r: Ray, scene: &Scene, tracer: &Tracer, rad: &mut Radiance)
+               -> Option<VisiblePoint> {
+    let mut path = PathState::new();
+    let mut r = r;
+    loop {
+        let Some(hr) = scene.world.hit(&r, 0.001, INFINITY) else {
+            // TODO(oren): this doesn't make a great deal of sense
+            rad.add_emission(&path, scene.background, None);
+            return None;
+        };
+        let emitted = hr.mat.emitted(&r, &hr, hr.u, hr.v, hr.p);
+        let light = if tracer.n_lights > 0 && !emitted.near_zero() {
+            light_index(scene, &r, &hr)
+        } else {
+            None
+        };
+        let Some(sr) = hr.mat.scatter(&r, &hr) else {
+            rad.add_emission(&path, emitted, light);
+            return None;
+        };
+        if let Some(spec_r) = sr.specular_ray {
+            let lobe = Lobe::of(&r, &hr, spec_r.dir, true);
+            path = path.bounce(lobe, sr.attenuation, &tracer.limits)?;
+            r = Ray::new(spec_r.origin, spec_r.dir, r.time);
+            continue;
+        }
+        if !hr.mat.deterministic() {
+            // photons can't be weighed by it, so carry on past it instead
+            let (scattered, value) = sample(&r, &hr, sr, tracer)?;
+            let lobe = Lobe::of(&r, &hr, scattered.dir, false);
+            path = path.bounce(lobe, value, &tracer.limits)?;
+            r = scattered;
+            continue;
+        }
+        rad.add_emission(&path, emitted, light);
+
+        let attenuation = sr.attenuation;
+        direct(scene, &r, &hr, sr, &path, tracer, rad);
+        return Some(VisiblePoint { hr, r_in: r, attenuation, beta: path.throughput });
+    }
+}
+
+// Send a photon out from the lights, leaving a copy wherever it lands on
+// something diffuse. Not on the first thing it hits, though: that light is
+// direct, which the camera paths already have.
+fn trace_photon(scene: &Scene, time: f64, tracer: &Tracer) -> Vec<(Point3, Photon)> {
+    let mut photons = vec![];
+    let Some(e) = Emission::sample(scene, time) else { return photons; };
+    let beta = e.beta();
+    let mut path = PathState::new();
+    let mut r = Ray::new(e.p, e.dir, time);
+    loop {
+        let Some(hr) = scene.world.hit(&r, 0.001, INFINITY) else { break; };
+        let Some(sr) = hr.mat.scatter(&r, &hr) else { break; };
+        if let Some(spec_r) = sr.specular_ray {
+            let lobe = Lobe::of(&r, &hr, spec_r.dir, true);
+            let Some(next) = path.bounce(lobe, sr.attenuation, &tracer.limits) else { break; };
+            path = next;
+            r = Ray::new(spec_r.origin, spec_r.dir, time);
+            continue;
+        }
+        if path.depth > 0 && hr.mat.deterministic() {
+            photons.push((hr.p, Photon {
+                dir: r.dir.unit_vector(),
+                beta: beta * path.throughput,
+                light: e.light,
+            }));
+        }
+
+        let Some((scattered, value)) = sample(&r, &hr, sr, tracer) else { break; };
+        let lobe = Lobe::of(&r, &hr, scattered.dir, false);
+        let Some(next) = path.bounce(lobe, value, &tracer.limits) else { break; };
+        path = next;
+        r = scattered;
+    }
+    photons
+}
+
+// Starting gather radius, if not given: a small fraction of the scene's size
+fn default_radius(scene: &Scene) -> f64 {
+    scene.world.bounding_box(0.0, 1.0)
+        .map(|bb| (bb.max - bb.min).len() / 200.0)
+        .filter(|r| r.is_finite() && *r > 0.0)
+        .unwrap_or(1.0)
+}
+
+// The image lit by photon mapping, a pass per sample per pixel, summed over
+// the passes. Pixels are in rows from the top.
+#[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+pub fn render(scene: &Scene, cam: &(dyn CameraModel + Sync + Send), settings: &RenderSettings,
+              tracer: &Tracer) -> Vec<Radiance> {
+    let (width, height) = (settings.image_width as usize, settings.image_height as usize);
+    let n_lights = tracer.n_lights;
+    let n_photons = settings.photons.unwrap_or(width * height);
+    let radius = settings.photon_radius.unwrap_or_else(|| default_radius(scene));
+    let mut pixels: Vec<PixelState> = (0..width * height).map(|_| PixelState {
+        radius,
+        n: 0.0,
+        tau: Radiance::zero(n_lights),
+        ld: Radiance::zero(n_lights),
+    }).collect();
+
+    let mut stderr = std::io::stderr();
+    for pass in 0..settings.samples_per_pixel {
+        write!(stderr, "\rPhoton passes remaining: {} ", settings.samples_per_pixel - pass);
+        stderr.flush();
+
+        let points: Vec<Option<VisiblePoint>> = pixels.par_iter_mut().enumerate().map(|(i, px)| {
+            let (x, y) = (i % width, height - 1 - i / width);
+            let u = (x as f64 + random::double()) / width as f64;
+            let v = (y as f64 + random::double()) / height as f64;
+            let r = cam.ray(u, v)?;
+            let mut rad = Radiance::zero(n_lights);
+            let vp = camera_path(r, scene, tracer, &mut rad);
+            let total = rad.total();
+            if total.is_nan() || !(total.r() + total.g() + total.b()).is_finite() {
+                tracer.bad_samples.fetch_add(1, Ordering::Relaxed);
+            } else {
+                px.ld = px.ld.clone().add(&rad);
+            }
+            vp
+        }).collect();
+
+        // photons go out at the times the camera saw, for motion blur
+        let times: Vec<f64> = points.iter().flatten().map(|vp| vp.r_in.time).collect();
+        let photons: Vec<(Point3, Photon)> = (0..n_photons).into_par_iter().flat_map_iter(|i| {
+            let time = if times.is_empty() { 0.0 } else { times[i % times.len()] };
+            trace_photon(scene, time, tracer)
+        }).collect();
+        let tree = KdTree::new(photons);
+
+        pixels.par_iter_mut().zip(points.par_iter()).for_each(|(px, vp)| {
+            if let Some(vp) = vp {
+                px.gather(&tree, vp, tracer);
+            }
+        });
+    }
+    writeln!(stderr);
+
+    // Each pass's photons spread the lights' power over the scene, so a
+    // pixel's flux over the area it was gathered from is its radiance; the
+    // caller divides by the passes.
+    pixels.into_iter().map(|px| {
+        let scale = 1.0 / (n_photons as f64 * PI * px.radius * px.radius);
+        px.ld.add(&px.tau.scale(Color(scale, scale, scale)))
+    }).collect()
+}
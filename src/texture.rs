@@ -294,6 +294,48 @@ impl Texture for Image {
     }
 }
 
+// Single channel of an image, e.g. an opacity map. Uses alpha if the image
+// has it, and the first channel otherwise.
+pub struct ImageFloat {
+    img: DynamicImage,
+    width: u32,
+    height: u32,
+    channel: usize,
+}
+
+impl ImageFloat {
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(fname: P) -> Self {
+        let img = image::open(fname.as_ref()).expect("File not found!");
+        eprintln!("{} - dimensions: {:?}; color: {:?}",
+                  fname.as_ref().display(), img.dimensions(), img.color());
+        let (width, height) = img.dimensions();
+        let channel = if img.color().has_alpha() { 3 } else { 0 };
+
+        Self {
+            img, width, height, channel,
+        }
+    }
+}
+
+impl From<ImageFloat> for Arc<dyn FloatTexture + Sync + Send> {
+    fn from(tt: ImageFloat) -> Arc<dyn FloatTexture + Sync + Send> {
+        Arc::new(tt)
+    }
+}
+
+impl FloatTexture for ImageFloat {
+    fn value(&self, u: f64, v: f64, _p: Point3) -> f64 {
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0); // flip V to image coords
+
+        let i = ((u * f64::from(self.width)) as u32).clamp(0, self.width - 1);
+        let j = ((v * f64::from(self.height)) as u32).clamp(0, self.height - 1);
+
+        Image::COLOR_SCALE * f64::from(self.img.get_pixel(i, j)[self.channel])
+    }
+}
+
 pub struct RandomBump {
     scale: f64,
     noise: Perlin,
@@ -353,4 +395,56 @@ impl FloatTexture for CheckerBump {
     }
 }
 
+pub struct ConstantFloat {
+    val: f64,
+}
+
+impl ConstantFloat {
+    #[must_use]
+    pub fn new(val: f64) -> Self {
+        Self { val }
+    }
+}
+
+impl From<ConstantFloat> for Arc<dyn FloatTexture + Sync + Send> {
+    fn from(tt: ConstantFloat) -> Arc<dyn FloatTexture + Sync + Send> {
+        Arc::new(tt)
+    }
+}
+
+impl FloatTexture for ConstantFloat {
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> f64 {
+        self.val
+    }
+}
+
+// Turbulence remapped to [min, max], e.g. for film thickness
+pub struct NoiseFloat {
+    noise: Perlin,
+    scale: f64,
+    min: f64,
+    max: f64,
+}
+
+impl NoiseFloat {
+    #[must_use]
+    pub fn new(scale: f64, min: f64, max: f64) -> Self {
+        Self {
+            noise: Perlin::new(),
+            scale, min, max,
+        }
+    }
+}
+
+impl From<NoiseFloat> for Arc<dyn FloatTexture + Sync + Send> {
+    fn from(tt: NoiseFloat) -> Arc<dyn FloatTexture + Sync + Send> {
+        Arc::new(tt)
+    }
+}
 
+impl FloatTexture for NoiseFloat {
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> f64 {
+        let t = self.noise.turb(self.scale * p, None).clamp(0.0, 1.0);
+        self.min + t * (self.max - self.min)
+    }
+}
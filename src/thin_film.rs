@@ -0,0 +1,116 @@
+use crate::vec3::Color;
+use crate::hit::HitRecord;
+use crate::texture::{FloatTexture,ConstantFloat};
+use crate::spectrum::{rgb_to_spectrum,spectrum_to_rgb,Wavelengths};
+use crate::util::PI;
+
+use std::sync::Arc;
+
+// A thin dielectric coating on top of a surface (soap bubbles, oil slicks,
+// anti-reflective lens coatings). Light bouncing between the top and bottom
+// of the film interferes with itself, so reflectance depends on wavelength
+// even when the substrate's doesn't.
+pub struct ThinFilm {
+    // in nanometers
+    thickness: Arc<dyn FloatTexture + Sync + Send>,
+    ior: f64,
+}
+
+// Fresnel amplitude coefficients (s and p polarized) going from IOR n1 into
+// n2, with cosines of the incident and transmitted angles.
+fn fresnel_amplitudes(n1: f64, n2: f64, cos1: f64, cos2: f64) -> (f64, f64) {
+    let rs = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+    let rp = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+    (rs, rp)
+}
+
+// Reflectance of the film, for amplitude coefficients r12 (top of the film)
+// and r23 (bottom) and the phase difference picked up by a round trip through
+// it. Summing every internal bounce gives the Airy formula.
+fn airy(r12: f64, r23: f64, delta: f64) -> f64 {
+    let cross = 2.0 * r12 * r23 * f64::cos(delta);
+    (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+}
+
+// A reflectance spectrum as RGB, or straight at the path's wavelengths in
+// spectral mode, where going through RGB would wash out the interference
+fn evaluate<F: Fn(f64) -> f64>(f: F, wavelengths: Option<&Wavelengths>) -> Color {
+    match wavelengths {
+        Some(w) => w.eval(f),
+        None => spectrum_to_rgb(f),
+    }
+}
+
+// cos of the refracted angle going from eta_i into eta_t, or None on TIR
+fn refracted_cos(eta_i: f64, eta_t: f64, cos_i: f64) -> Option<f64> {
+    let sin2_i = f64::max(0.0, 1.0 - cos_i * cos_i);
+    let sin2_t = (eta_i / eta_t) * (eta_i / eta_t) * sin2_i;
+    if sin2_t >= 1.0 {
+        None
+    } else {
+        Some((1.0 - sin2_t).sqrt())
+    }
+}
+
+impl ThinFilm {
+    #[must_use]
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        Self::from_texture(ConstantFloat::new(thickness).into(), ior)
+    }
+
+    #[must_use]
+    pub fn from_texture(thickness: Arc<dyn FloatTexture + Sync + Send>, ior: f64) -> Self {
+        Self { thickness, ior }
+    }
+
+    // Returns the film's cos and the phase difference at lambda, or None if
+    // light can't enter the film at all.
+    fn film_phase(&self, rec: &HitRecord, eta_i: f64, cos_i: f64)
+                  -> Option<(f64, impl Fn(f64) -> f64)> {
+        let cos_f = refracted_cos(eta_i, self.ior, cos_i)?;
+        let d = self.thickness.value(rec.u, rec.v, rec.p).max(0.0);
+        let opd = 4.0 * PI * self.ior * d * cos_f;
+        Some((cos_f, move |lambda: f64| opd / lambda))
+    }
+
+    // Reflectance of the coated interface going from eta_i into a dielectric
+    // of eta_t. At the given wavelengths, if there are any, otherwise RGB.
+    #[must_use]
+    pub fn dielectric_reflectance(&self, rec: &HitRecord, cos_i: f64, eta_i: f64, eta_t: f64,
+                                  wavelengths: Option<&Wavelengths>) -> Color {
+        let Some(cos_t) = refracted_cos(eta_i, eta_t, cos_i) else {
+            return Color(1.0, 1.0, 1.0);
+        };
+        let Some((cos_f, phase)) = self.film_phase(rec, eta_i, cos_i) else {
+            return Color(1.0, 1.0, 1.0);
+        };
+
+        let (r12s, r12p) = fresnel_amplitudes(eta_i, self.ior, cos_i, cos_f);
+        let (r23s, r23p) = fresnel_amplitudes(self.ior, eta_t, cos_f, cos_t);
+
+        evaluate(|lambda| {
+            let delta = phase(lambda);
+            0.5 * (airy(r12s, r23s, delta) + airy(r12p, r23p, delta))
+        }, wavelengths)
+    }
+
+    // Reflectance of a coated conductor with (normal incidence) reflectance
+    // r0. Without a complex IOR to go on, treat the metal as reflecting with
+    // a half-wave phase shift, which is what a perfect conductor does. At the
+    // given wavelengths, if there are any, otherwise RGB.
+    #[must_use]
+    pub fn conductor_reflectance(&self, rec: &HitRecord, cos_i: f64, eta_i: f64, r0: Color,
+                                 wavelengths: Option<&Wavelengths>) -> Color {
+        let Some((cos_f, phase)) = self.film_phase(rec, eta_i, cos_i) else {
+            return Color(1.0, 1.0, 1.0);
+        };
+
+        let (r12s, r12p) = fresnel_amplitudes(eta_i, self.ior, cos_i, cos_f);
+
+        evaluate(|lambda| {
+            let r23 = rgb_to_spectrum(r0, lambda).min(1.0).sqrt();
+            let delta = phase(lambda);
+            0.5 * (airy(r12s, -r23, delta) + airy(r12p, r23, delta))
+        }, wavelengths)
+    }
+}
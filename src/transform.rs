@@ -0,0 +1,661 @@
+use crate::vec3::{Vec3,Point3,cross,dot};
+use crate::ray::Ray;
+use crate::hit::{HitRecord,Hittable};
+use crate::aabb::AABB;
+use crate::material::Material;
+use crate::util;
+
+use std::ops;
+use std::sync::Arc;
+
+// Row-major 4x4 matrix for affine transforms of points (w = 1) and vectors
+// (w = 0).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mat4 {
+    #[must_use]
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Self { m }
+    }
+
+    #[must_use]
+    pub fn translate(offset: Vec3) -> Self {
+        let mut t = Self::identity();
+        t.m[0][3] = offset.x();
+        t.m[1][3] = offset.y();
+        t.m[2][3] = offset.z();
+        t
+    }
+
+    #[must_use]
+    pub fn scale(s: Vec3) -> Self {
+        let mut t = Self::identity();
+        t.m[0][0] = s.x();
+        t.m[1][1] = s.y();
+        t.m[2][2] = s.z();
+        t
+    }
+
+    #[must_use]
+    pub fn uniform_scale(s: f64) -> Self {
+        Self::scale(Vec3(s, s, s))
+    }
+
+    // Rotation by angle (in degrees) about an arbitrary axis through the
+    // origin, counter-clockwise looking down the axis.
+    #[must_use]
+    pub fn rotate(axis: Vec3, angle: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = util::degrees_to_radians(angle).sin_cos();
+        let mut t = Self::identity();
+        t.m[0][0] = a.x() * a.x() + (1.0 - a.x() * a.x()) * cos;
+        t.m[0][1] = a.x() * a.y() * (1.0 - cos) - a.z() * sin;
+        t.m[0][2] = a.x() * a.z() * (1.0 - cos) + a.y() * sin;
+        t.m[1][0] = a.x() * a.y() * (1.0 - cos) + a.z() * sin;
+        t.m[1][1] = a.y() * a.y() + (1.0 - a.y() * a.y()) * cos;
+        t.m[1][2] = a.y() * a.z() * (1.0 - cos) - a.x() * sin;
+        t.m[2][0] = a.x() * a.z() * (1.0 - cos) - a.y() * sin;
+        t.m[2][1] = a.y() * a.z() * (1.0 - cos) + a.x() * sin;
+        t.m[2][2] = a.z() * a.z() + (1.0 - a.z() * a.z()) * cos;
+        t
+    }
+
+    #[must_use]
+    pub fn rotate_x(angle: f64) -> Self {
+        Self::rotate(Vec3(1.0, 0.0, 0.0), angle)
+    }
+
+    #[must_use]
+    pub fn rotate_y(angle: f64) -> Self {
+        Self::rotate(Vec3(0.0, 1.0, 0.0), angle)
+    }
+
+    #[must_use]
+    pub fn rotate_z(angle: f64) -> Self {
+        Self::rotate(Vec3(0.0, 0.0, 1.0), angle)
+    }
+
+    // Shear: each of x, y, z picks up some of the other two coordinates, e.g.
+    // xy is how much of y gets added to x.
+    #[must_use]
+    pub fn shear(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        let mut t = Self::identity();
+        t.m[0][1] = xy;
+        t.m[0][2] = xz;
+        t.m[1][0] = yx;
+        t.m[1][2] = yz;
+        t.m[2][0] = zx;
+        t.m[2][1] = zy;
+        t
+    }
+
+    // Camera-style frame: maps the origin to eye and -z to look at target
+    #[must_use]
+    pub fn look_at(eye: Point3, target: Point3, up: Vec3) -> Self {
+        let w = (eye - target).unit_vector();
+        let u = cross(up, w).unit_vector();
+        let v = cross(w, u);
+        let mut t = Self::identity();
+        for i in 0..3 {
+            t.m[i][0] = u[i];
+            t.m[i][1] = v[i];
+            t.m[i][2] = w[i];
+            t.m[i][3] = eye[i];
+        }
+        t
+    }
+
+    #[must_use]
+    pub fn transpose(&self) -> Self {
+        let mut t = Self::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                t.m[i][j] = self.m[j][i];
+            }
+        }
+        t
+    }
+
+    // Determinant of the upper 3x3, i.e. how much the transform scales volumes
+    #[must_use]
+    pub fn det3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. None if singular.
+    #[must_use]
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|i, j| {
+                a[*i][col].abs().total_cmp(&a[*j][col].abs())
+            })?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = a[col][col];
+            for j in 0..4 {
+                a[col][j] /= p;
+                inv[col][j] /= p;
+            }
+            for i in 0..4 {
+                if i != col {
+                    let f = a[i][col];
+                    for j in 0..4 {
+                        a[i][j] -= f * a[col][j];
+                        inv[i][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self { m: inv })
+    }
+
+    #[must_use]
+    pub fn point(&self, p: Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3];
+        let y = m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3];
+        let z = m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3];
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if (w - 1.0).abs() < f64::EPSILON {
+            Point3(x, y, z)
+        } else {
+            Point3(x, y, z) / w
+        }
+    }
+
+    #[must_use]
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Normals transform by the inverse transpose, so call this on the
+    // *inverse* of the matrix that moves the surface.
+    #[must_use]
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z(),
+        )
+    }
+
+    #[must_use]
+    pub fn ray(&self, r: &Ray) -> Ray {
+        Ray {
+            origin: self.point(r.origin),
+            dir: self.vector(r.dir),
+            time: r.time,
+            wavelengths: r.wavelengths,
+        }
+    }
+
+    // Box around the eight transformed corners
+    #[must_use]
+    pub fn bbox(&self, bb: &AABB) -> AABB {
+        let mut min = Point3(util::INFINITY, util::INFINITY, util::INFINITY);
+        let mut max = Point3(util::NEG_INFINITY, util::NEG_INFINITY, util::NEG_INFINITY);
+        for i in 0..8 {
+            let corner = Point3(
+                if i & 1 == 0 { bb.min.x() } else { bb.max.x() },
+                if i & 2 == 0 { bb.min.y() } else { bb.max.y() },
+                if i & 4 == 0 { bb.min.z() } else { bb.max.z() },
+            );
+            let p = self.point(corner);
+            for c in 0..3 {
+                min[c] = f64::min(min[c], p[c]);
+                max[c] = f64::max(max[c], p[c]);
+            }
+        }
+        AABB { min, max }
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Self { m: t }
+    }
+}
+
+// World space direction v in object space, with the solid angle pdf Jacobian
+fn direction_to_object(m: &Mat4, m_inv: &Mat4, v: Vec3) -> (Vec3, f64) {
+    let v_obj = m_inv.vector(v.unit_vector());
+    let len = v_obj.len();
+    let jacobian = 1.0 / (m.det3().abs() * len * len * len);
+    (v_obj / len, jacobian)
+}
+
+// Hit obj, which is placed in the world by m (with inverse m_inv)
+fn hit_transformed(obj: &Arc<dyn Hittable + Sync + Send>, m: &Mat4, m_inv: &Mat4,
+                   r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // The object space direction isn't unit length, so t carries over
+    let mut hr = obj.hit(&m_inv.ray(r), t_min, t_max)?;
+
+    let out_norm = if hr.front_face { hr.norm } else { -hr.norm };
+    let out_norm = m_inv.normal(out_norm).unit_vector();
+
+    hr.p = m.point(hr.p);
+    hr.set_face_normal(r, out_norm);
+
+    let mut n = m_inv.normal(hr.shading_geo.n).unit_vector();
+    if dot(n, hr.norm) < 0.0 {
+        n = -n;
+    }
+    hr.shading_geo.n = n;
+    hr.shading_geo.dpdu = m.vector(hr.shading_geo.dpdu);
+    hr.shading_geo.dpdv = m.vector(hr.shading_geo.dpdv);
+    Some(hr)
+}
+
+// Places a Hittable in the world with an arbitrary affine transform. The
+// wrapped object is shared, so many Transforms of one (say) mesh BVH are
+// cheap instances of it.
+pub struct Transform {
+    obj: Arc<dyn Hittable + Sync + Send>,
+    // object to world, and back
+    m: Mat4,
+    m_inv: Mat4,
+    bbox: Option<AABB>,
+}
+
+impl Transform {
+    /// # Panics
+    ///
+    /// Panics if the matrix isn't invertible
+    #[must_use]
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, m: Mat4) -> Self {
+        let m_inv = m.inverse().expect("Transform matrix is singular");
+        let bbox = obj.bounding_box(0.0, 1.0).map(|bb| m.bbox(&bb));
+        Self { obj, m, m_inv, bbox }
+    }
+
+    #[must_use]
+    pub fn matrix(&self) -> Mat4 {
+        self.m
+    }
+
+}
+
+impl From<Transform> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: Transform) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        hit_transformed(&self.obj, &self.m, &self.m_inv, r, t_min, t_max)
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.obj.materials(f);
+    }
+
+    fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: Point3, v: Vec3) -> f64 {
+        let (v_obj, jacobian) = direction_to_object(&self.m, &self.m_inv, v);
+        self.obj.pdf_value(self.m_inv.point(origin), v_obj) * jacobian
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        self.m.vector(self.obj.random(self.m_inv.point(origin)))
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.obj.transmittance(&self.m_inv.ray(r), t_min, t_max)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Quaternion {
+    pub v: Vec3,
+    pub w: f64,
+}
+
+impl Quaternion {
+    #[must_use]
+    pub fn dot(&self, other: &Self) -> f64 {
+        dot(self.v, other.v) + self.w * other.w
+    }
+
+    #[must_use]
+    pub fn normalize(&self) -> Self {
+        let len = self.dot(self).sqrt();
+        Self { v: self.v / len, w: self.w / len }
+    }
+
+    // From the rotation in the upper 3x3 of m (Shoemake's method)
+    #[must_use]
+    pub fn from_matrix(m: &Mat4) -> Self {
+        let m = &m.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt();
+            let k = 0.5 / s;
+            Self {
+                v: Vec3(
+                    (m[2][1] - m[1][2]) * k,
+                    (m[0][2] - m[2][0]) * k,
+                    (m[1][0] - m[0][1]) * k,
+                ),
+                w: 0.5 * s,
+            }
+        } else {
+            // work from the largest diagonal element
+            let i = if m[1][1] > m[0][0] { 1 } else { 0 };
+            let i = if m[2][2] > m[i][i] { 2 } else { i };
+            let j = (i + 1) % 3;
+            let k = (j + 1) % 3;
+            let s = (m[i][i] - (m[j][j] + m[k][k]) + 1.0).sqrt();
+            let mut q = [0.0; 3];
+            q[i] = 0.5 * s;
+            let s = 0.5 / s;
+            q[j] = (m[j][i] + m[i][j]) * s;
+            q[k] = (m[k][i] + m[i][k]) * s;
+            Self {
+                v: Vec3(q[0], q[1], q[2]),
+                w: (m[k][j] - m[j][k]) * s,
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn to_matrix(&self) -> Mat4 {
+        let (x, y, z, w) = (self.v.x(), self.v.y(), self.v.z(), self.w);
+        let mut t = Mat4::identity();
+        t.m[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        t.m[0][1] = 2.0 * (x * y - z * w);
+        t.m[0][2] = 2.0 * (x * z + y * w);
+        t.m[1][0] = 2.0 * (x * y + z * w);
+        t.m[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        t.m[1][2] = 2.0 * (y * z - x * w);
+        t.m[2][0] = 2.0 * (x * z - y * w);
+        t.m[2][1] = 2.0 * (y * z + x * w);
+        t.m[2][2] = 1.0 - 2.0 * (x * x + y * y);
+        t
+    }
+
+    // Constant angular velocity from self (t = 0) to other (t = 1)
+    #[must_use]
+    pub fn slerp(&self, other: &Self, t: f64) -> Self {
+        let cos_theta = self.dot(other);
+        if cos_theta > 0.9995 {
+            // nearly parallel; lerp is fine and avoids dividing by ~0
+            return Self {
+                v: (1.0 - t) * self.v + t * other.v,
+                w: (1.0 - t) * self.w + t * other.w,
+            }.normalize();
+        }
+        let theta = cos_theta.clamp(-1.0, 1.0).acos();
+        let theta_p = theta * t;
+        // the part of other perpendicular to self
+        let perp = Self {
+            v: other.v - cos_theta * self.v,
+            w: other.w - cos_theta * self.w,
+        }.normalize();
+        Self {
+            v: f64::cos(theta_p) * self.v + f64::sin(theta_p) * perp.v,
+            w: f64::cos(theta_p) * self.w + f64::sin(theta_p) * perp.w,
+        }
+    }
+}
+
+// A transform split into translation, rotation and everything else (scale
+// and shear), so that each can be interpolated on its own.
+#[derive(Copy, Clone)]
+struct Decomposed {
+    t: Vec3,
+    r: Quaternion,
+    s: Mat4,
+}
+
+impl Decomposed {
+    // M = T R S, with R from the polar decomposition of the upper 3x3
+    fn new(m: &Mat4) -> Self {
+        let t = Vec3(m.m[0][3], m.m[1][3], m.m[2][3]);
+        let mut a = *m;
+        for row in a.m.iter_mut().take(3) {
+            row[3] = 0.0;
+        }
+
+        // average R with its inverse transpose until it stops changing
+        let mut r = a;
+        for _ in 0..100 {
+            let Some(r_it) = r.transpose().inverse() else { break; };
+            let mut next = r;
+            let mut norm: f64 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    next.m[i][j] = 0.5 * (r.m[i][j] + r_it.m[i][j]);
+                    norm = norm.max((next.m[i][j] - r.m[i][j]).abs());
+                }
+            }
+            r = next;
+            if norm < 1e-10 {
+                break;
+            }
+        }
+        // a mirroring transform leaves R a reflection; move that into S
+        if r.det3() < 0.0 {
+            for row in r.m.iter_mut().take(3) {
+                for x in row.iter_mut().take(3) {
+                    *x = -*x;
+                }
+            }
+        }
+        let s = r.transpose() * a;
+        Self { t, r: Quaternion::from_matrix(&r).normalize(), s }
+    }
+
+    fn interpolate(&self, other: &Self, t: f64) -> Mat4 {
+        let mut s = Mat4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                s.m[i][j] = (1.0 - t) * self.s.m[i][j] + t * other.s.m[i][j];
+            }
+        }
+        Mat4::translate((1.0 - t) * self.t + t * other.t)
+            * self.r.slerp(&other.r, t).to_matrix()
+            * s
+    }
+}
+
+// A Hittable whose transform changes over the shutter interval, for motion
+// blur on anything (meshes, instances, ...). Keyframed transforms are
+// decomposed, and interpolated at each ray's time: translation and
+// scale/shear linearly, rotation by quaternion slerp. Before the first
+// keyframe and after the last, the object holds still.
+pub struct AnimatedTransform {
+    obj: Arc<dyn Hittable + Sync + Send>,
+    keys: Vec<(f64, Decomposed)>,
+    // Where it is at time 0, and back, for light sampling. pdf_value and
+    // random aren't told a time, so they sample the object as it is then;
+    // the two agree, so the estimate is still right, just noisier while the
+    // object moves. None if the transform is singular then.
+    still: Option<(Mat4, Mat4)>,
+}
+
+impl AnimatedTransform {
+    // Bounding boxes are found by sampling the motion this many times per
+    // keyframe interval, then padded for the arc swept between samples
+    const BBOX_SAMPLES : usize = 64;
+
+    /// # Panics
+    ///
+    /// Panics if there are no keyframes
+    #[must_use]
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, keyframes: &[(f64, Mat4)]) -> Self {
+        assert!(!keyframes.is_empty(), "AnimatedTransform needs a keyframe");
+        let mut keys: Vec<(f64, Decomposed)> = keyframes.iter()
+            .map(|(time, m)| (*time, Decomposed::new(m)))
+            .collect();
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // keep each rotation on the short way round from the one before
+        for i in 1..keys.len() {
+            if keys[i - 1].1.r.dot(&keys[i].1.r) < 0.0 {
+                let r = keys[i].1.r;
+                keys[i].1.r = Quaternion { v: -r.v, w: -r.w };
+            }
+        }
+        let mut tr = Self { obj, keys, still: None };
+        let m = tr.matrix(0.0);
+        tr.still = m.inverse().map(|m_inv| (m, m_inv));
+        tr
+    }
+
+    // Just a start and an end
+    #[must_use]
+    pub fn between(obj: Arc<dyn Hittable + Sync + Send>,
+                   time0: f64, m0: Mat4, time1: f64, m1: Mat4) -> Self {
+        Self::new(obj, &[(time0, m0), (time1, m1)])
+    }
+
+    #[must_use]
+    pub fn matrix(&self, time: f64) -> Mat4 {
+        let (k0, k1, t) = self.keys_at(time);
+        k0.interpolate(k1, t)
+    }
+
+    // Just the rotation part of matrix(time)
+    fn rotation(&self, time: f64) -> Quaternion {
+        let (k0, k1, t) = self.keys_at(time);
+        k0.r.slerp(&k1.r, t)
+    }
+
+    // The keyframes either side of time, and how far it is from the first
+    // to the second
+    fn keys_at(&self, time: f64) -> (&Decomposed, &Decomposed, f64) {
+        let first = &self.keys[0];
+        let last = &self.keys[self.keys.len() - 1];
+        if time <= first.0 {
+            return (&first.1, &first.1, 0.0);
+        }
+        if time >= last.0 {
+            return (&last.1, &last.1, 0.0);
+        }
+        let i = self.keys.partition_point(|k| k.0 <= time) - 1;
+        let (t0, k0) = &self.keys[i];
+        let (t1, k1) = &self.keys[i + 1];
+        (k0, k1, (time - t0) / (t1 - t0))
+    }
+}
+
+impl From<AnimatedTransform> for Arc<dyn Hittable + Sync + Send> {
+    fn from(hh: AnimatedTransform) -> Arc<dyn Hittable + Sync + Send> {
+        Arc::new(hh)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let m = self.matrix(r.time);
+        let m_inv = m.inverse()?;
+        hit_transformed(&self.obj, &m, &m_inv, r, t_min, t_max)
+    }
+
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        self.obj.materials(f);
+    }
+
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        let bb = self.obj.bounding_box(time0, time1)?;
+
+        // sample times: the ends, every keyframe in between, and evenly
+        // between those
+        let mut times = vec![time0];
+        times.extend(self.keys.iter().map(|k| k.0).filter(|t| *t > time0 && *t < time1));
+        times.push(time1);
+        let samples: Vec<f64> = times.windows(2).flat_map(|w| {
+            (0..Self::BBOX_SAMPLES).map(move |i| {
+                w[0] + (w[1] - w[0]) * i as f64 / Self::BBOX_SAMPLES as f64
+            })
+        }).chain(std::iter::once(time1)).collect();
+
+        let mut result: Option<AABB> = None;
+        let mut prev: Option<Quaternion> = None;
+        for time in samples {
+            let m = self.matrix(time);
+            let q1 = self.rotation(time);
+            let mut bb_t = m.bbox(&bb);
+
+            // Between two samples, a point can bulge out past the chord
+            // joining its two positions by r (1 - cos(theta / 2))
+            if let Some(q0) = prev {
+                let theta = 2.0 * q0.dot(&q1).abs().clamp(-1.0, 1.0).acos();
+                let center = m.point(Point3(0.0, 0.0, 0.0));
+                let radius = (0..3).map(|c| {
+                    f64::max((bb_t.min[c] - center[c]).abs(), (bb_t.max[c] - center[c]).abs())
+                }).map(|x| x * x).sum::<f64>().sqrt();
+                let pad = radius * (1.0 - f64::cos(0.5 * theta));
+                bb_t.min += -Vec3(pad, pad, pad);
+                bb_t.max += Vec3(pad, pad, pad);
+            }
+            prev = Some(q1);
+
+            result = Some(match result {
+                Some(acc) => AABB::surrounding_box(acc, bb_t),
+                None => bb_t,
+            });
+        }
+        result
+    }
+
+    fn pdf_value(&self, origin: Point3, v: Vec3) -> f64 {
+        let Some((m, m_inv)) = &self.still else { return 0.0; };
+        let (v_obj, jacobian) = direction_to_object(m, m_inv, v);
+        self.obj.pdf_value(m_inv.point(origin), v_obj) * jacobian
+    }
+
+    fn random(&self, origin: Vec3) -> Vec3 {
+        match &self.still {
+            Some((m, m_inv)) => m.vector(self.obj.random(m_inv.point(origin))),
+            None => Vec3(1.0, 0.0, 0.0),
+        }
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self.matrix(r.time).inverse() {
+            Some(m_inv) => self.obj.transmittance(&m_inv.ray(r), t_min, t_max),
+            None => 1.0,
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::vec3::{Vec3,Point3,dot,cross};
 use crate::ray::Ray;
 use crate::hit::{Hittable,HitRecord};
-// use crate::material::Material;
+use crate::material::Material;
 use crate::aabb::AABB;
 use crate::triangle_mesh::TriangleMesh;
 use crate::onb::OrthoNormalBasis;
@@ -171,6 +171,10 @@ impl Hittable for Triangle {
         Some(hr)
     }
 
+    fn materials(&self, f: &mut dyn FnMut(&Arc<dyn Material + Sync + Send>)) {
+        f(&self.mesh.mat);
+    }
+
     // Give the smallest reasonable AABB for the Hittable
     fn bounding_box(&self, _time0: f64, _time1: f64) -> Option<AABB> {
         let (a, b, c) = (self.vertex(0), self.vertex(1), self.vertex(2));
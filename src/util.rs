@@ -1,3 +1,5 @@
+use crate::vec3::Vec3;
+
 
 pub const INFINITY : f64 = f64::INFINITY;
 pub const NEG_INFINITY : f64 = f64::NEG_INFINITY;
@@ -14,6 +16,23 @@ pub fn degrees_to_radians(deg : f64) -> f64 {
     d * std::f64::consts::PI / 180.0
 }
 
+// Deterministic value in [0,1) for a hit point and incoming direction. Lets
+// stochastic choices made at a hit (e.g. which of two materials to use) come
+// out the same each time they're revisited during shading.
+#[must_use]
+pub fn hash_float(p: Vec3, d: Vec3) -> f64 {
+    // splitmix64 finalizer, folded over the bits of each coordinate
+    let mut h: u64 = 0x9e37_79b9_7f4a_7c15;
+    for x in [p.x(), p.y(), p.z(), d.x(), d.y(), d.z()] {
+        h ^= x.to_bits();
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        h ^= h >> 31;
+    }
+    // top 53 bits as a double in [0,1)
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
 #[must_use]
 pub fn clamp(x : f64, min: f64, max: f64) -> f64 {
     match x {